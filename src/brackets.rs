@@ -0,0 +1,111 @@
+use crate::Highlight;
+use crate::Row;
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+// how many rows either way are searched for a partner, so an unbalanced
+// bracket in a huge file doesn't scan all of it on every redraw.
+const MAX_SCAN_ROWS: usize = 2000;
+
+pub fn is_bracket(c: char) -> bool {
+    PAIRS.iter().any(|(open, close)| c == *open || c == *close)
+}
+
+// brackets in strings and comments only pair up with each other.
+fn is_code(row: &Row, x: usize, tab_stop: usize) -> bool {
+    let highlight = row.highlight.get(row.render_index(x, tab_stop));
+    !matches!(
+        highlight,
+        Some(Highlight::String)
+            | Some(Highlight::Escape)
+            | Some(Highlight::Comment)
+            | Some(Highlight::MultiComment)
+            | Some(Highlight::DocComment)
+            | Some(Highlight::Todo)
+    )
+}
+
+/// Finds the partner of the bracket at `at`, both `(x, y)`, using the
+/// highlighting of `rows` to tell code from strings and comments.
+pub fn find_match(rows: &[Row], at: (usize, usize), tab_stop: usize) -> Option<(usize, usize)> {
+    let c = *rows.get(at.1)?.buf.get(at.0)?;
+    let (open, close) = *PAIRS
+        .iter()
+        .find(|(open, close)| c == *open || c == *close)?;
+    let code = is_code(&rows[at.1], at.0, tab_stop);
+    let mut depth = 0;
+    let mut is_partner = |x: usize, y: usize| {
+        let other = rows[y].buf[x];
+        if (other != open && other != close) || is_code(&rows[y], x, tab_stop) != code {
+            return false;
+        }
+        if other == c {
+            depth += 1;
+            false
+        } else {
+            depth -= 1;
+            depth == 0
+        }
+    };
+    if c == open {
+        let last = (at.1 + MAX_SCAN_ROWS).min(rows.len() - 1);
+        for (y, row) in rows.iter().enumerate().take(last + 1).skip(at.1) {
+            let from = if y == at.1 { at.0 } else { 0 };
+            if let Some(x) = (from..row.buf.len()).find(|x| is_partner(*x, y)) {
+                return Some((x, y));
+            }
+        }
+    } else {
+        let first = at.1.saturating_sub(MAX_SCAN_ROWS);
+        for y in (first..=at.1).rev() {
+            let to = if y == at.1 {
+                at.0 + 1
+            } else {
+                rows[y].buf.len()
+            };
+            if let Some(x) = (0..to).rev().find(|x| is_partner(*x, y)) {
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // `s` and `c` in `classes` mark string and comment chars.
+    fn row(line: &str, classes: &str) -> Row {
+        let mut row = Row::from(&String::from(line));
+        row.render = row.buf.clone();
+        row.highlight = classes
+            .chars()
+            .map(|c| match c {
+                's' => Highlight::String,
+                'c' => Highlight::Comment,
+                _ => Highlight::Normal,
+            })
+            .collect();
+        row
+    }
+
+    #[test]
+    fn test_find_match() {
+        let rows = vec![
+            row("fn f(a: [u8; 2]) {", ""),
+            row("    g(\")\"); // (", "      sss   ccccc"),
+            row("}", ""),
+        ];
+        assert_eq!(find_match(&rows, (4, 0), 4), Some((15, 0)));
+        assert_eq!(find_match(&rows, (15, 0), 4), Some((4, 0)));
+        assert_eq!(find_match(&rows, (8, 0), 4), Some((14, 0)));
+        assert_eq!(find_match(&rows, (17, 0), 4), Some((0, 2)));
+        assert_eq!(find_match(&rows, (0, 2), 4), Some((17, 0)));
+        assert_eq!(find_match(&rows, (5, 1), 4), Some((9, 1)));
+        // the bracket in the comment has no partner in a comment.
+        assert_eq!(find_match(&rows, (15, 1), 4), None);
+        assert_eq!(find_match(&rows, (0, 0), 4), None);
+        assert_eq!(find_match(&[row("((", "")], (0, 0), 4), None);
+    }
+}
@@ -0,0 +1,653 @@
+use crate::prompt::complete_path;
+use crate::syntax::FileType;
+use crate::transform::SORT_OPTIONS;
+
+/// A named command that can be typed on the command line.
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub usage: &'static str,
+    min_args: usize,
+    max_args: usize,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "save",
+        aliases: &["w", "write"],
+        usage: "save [file]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "quit",
+        aliases: &["q"],
+        usage: "quit",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "goto",
+        aliases: &[],
+        usage: "goto [line]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "set",
+        aliases: &[],
+        usage: "set <option> <value>",
+        min_args: 2,
+        max_args: 2,
+    },
+    Command {
+        name: "filetype",
+        aliases: &["ft"],
+        usage: "filetype [name|none]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "find",
+        aliases: &[],
+        usage: "find",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "find-next",
+        aliases: &[],
+        usage: "find-next",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "find-previous",
+        aliases: &[],
+        usage: "find-previous",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "replace",
+        aliases: &[],
+        usage: "replace <from> <to>",
+        min_args: 2,
+        max_args: 2,
+    },
+    Command {
+        name: "sort",
+        aliases: &[],
+        usage: "sort [reverse] [numeric] [ignore-case]",
+        min_args: 0,
+        max_args: 3,
+    },
+    Command {
+        name: "uniq",
+        aliases: &[],
+        usage: "uniq",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "upcase",
+        aliases: &[],
+        usage: "upcase",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "downcase",
+        aliases: &[],
+        usage: "downcase",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "titlecase",
+        aliases: &[],
+        usage: "titlecase",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "trim-whitespace",
+        aliases: &[],
+        usage: "trim-whitespace",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "tabs-to-spaces",
+        aliases: &[],
+        usage: "tabs-to-spaces",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "spaces-to-tabs",
+        aliases: &[],
+        usage: "spaces-to-tabs",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "filter",
+        aliases: &[],
+        usage: "filter [shell command]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "move-left",
+        aliases: &[],
+        usage: "move-left",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "move-right",
+        aliases: &[],
+        usage: "move-right",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "move-up",
+        aliases: &[],
+        usage: "move-up",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "move-down",
+        aliases: &[],
+        usage: "move-down",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "delete-backward",
+        aliases: &[],
+        usage: "delete-backward",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "undo",
+        aliases: &[],
+        usage: "undo",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "redo",
+        aliases: &[],
+        usage: "redo",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "duplicate-line",
+        aliases: &[],
+        usage: "duplicate-line",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "delete-line",
+        aliases: &[],
+        usage: "delete-line",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "move-line-up",
+        aliases: &[],
+        usage: "move-line-up",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "move-line-down",
+        aliases: &[],
+        usage: "move-line-down",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "join-lines",
+        aliases: &[],
+        usage: "join-lines",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "add-cursor-above",
+        aliases: &[],
+        usage: "add-cursor-above",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "add-cursor-below",
+        aliases: &[],
+        usage: "add-cursor-below",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "add-next-occurrence",
+        aliases: &[],
+        usage: "add-next-occurrence",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "toggle-comment",
+        aliases: &[],
+        usage: "toggle-comment",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "match-bracket",
+        aliases: &[],
+        usage: "match-bracket",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "indent",
+        aliases: &[],
+        usage: "indent",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "dedent",
+        aliases: &[],
+        usage: "dedent",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "line-start",
+        aliases: &[],
+        usage: "line-start",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "line-end",
+        aliases: &[],
+        usage: "line-end",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "kill-line",
+        aliases: &[],
+        usage: "kill-line",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "kill-region",
+        aliases: &[],
+        usage: "kill-region",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "copy-region",
+        aliases: &[],
+        usage: "copy-region",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "yank",
+        aliases: &[],
+        usage: "yank",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "yank-pop",
+        aliases: &[],
+        usage: "yank-pop",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "paste-history",
+        aliases: &[],
+        usage: "paste-history [n]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "copy-to-register",
+        aliases: &[],
+        usage: "copy-to-register [register]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "insert-register",
+        aliases: &[],
+        usage: "insert-register [register]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "set-mark",
+        aliases: &[],
+        usage: "set-mark",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "cancel",
+        aliases: &[],
+        usage: "cancel",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "rectangle-mark",
+        aliases: &[],
+        usage: "rectangle-mark",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "copy-rectangle",
+        aliases: &[],
+        usage: "copy-rectangle",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "kill-rectangle",
+        aliases: &[],
+        usage: "kill-rectangle",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "yank-rectangle",
+        aliases: &[],
+        usage: "yank-rectangle",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "string-rectangle",
+        aliases: &[],
+        usage: "string-rectangle [text]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "record-macro",
+        aliases: &[],
+        usage: "record-macro [register]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "play-macro",
+        aliases: &[],
+        usage: "play-macro [register] [count]",
+        min_args: 0,
+        max_args: 2,
+    },
+    Command {
+        name: "play-macro-lines",
+        aliases: &[],
+        usage: "play-macro-lines [register]",
+        min_args: 0,
+        max_args: 1,
+    },
+    Command {
+        name: "save-macros",
+        aliases: &[],
+        usage: "save-macros",
+        min_args: 0,
+        max_args: 0,
+    },
+    Command {
+        name: "command-line",
+        aliases: &[],
+        usage: "command-line",
+        min_args: 0,
+        max_args: 0,
+    },
+];
+
+/// Options understood by `set`.
+pub const OPTIONS: &[&str] = &[
+    "tabwidth",
+    "shiftwidth",
+    "expandtab",
+    "autopairs",
+    "editing",
+];
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS
+        .iter()
+        .find(|command| command.name == name || command.aliases.contains(&name))
+}
+
+/// Splits a command line into the command and its arguments. A bare number
+/// is short for `goto <number>`.
+pub fn parse(line: &str) -> Result<(&'static Command, Vec<String>), String> {
+    let line = line.trim();
+    let (name, rest) = match line.strip_prefix('!') {
+        Some(rest) => ("filter", rest),
+        None => line.split_at(line.find(char::is_whitespace).unwrap_or(line.len())),
+    };
+    // a shell command is passed on as typed, quotes and all.
+    if let Some(command) = find(name).filter(|command| command.name == "filter") {
+        let rest = rest.trim();
+        let args = match rest {
+            "" => vec![],
+            _ => vec![String::from(rest)],
+        };
+        return Ok((command, args));
+    }
+    let mut words = split_args(line)?;
+    if words.is_empty() {
+        return Err(String::from("No command given"));
+    }
+    if words[0].chars().all(|c| c.is_ascii_digit()) {
+        words.insert(0, String::from("goto"));
+    }
+    let command = find(&words[0]).ok_or_else(|| format!("Unknown command: {}", words[0]))?;
+    let args = words.split_off(1);
+    if args.len() < command.min_args || args.len() > command.max_args {
+        return Err(format!("Usage: {}", command.usage));
+    }
+    Ok((command, args))
+}
+
+/// Splits on whitespace, keeping `"quoted words"` together. Inside quotes
+/// `\"` and `\\` stand for themselves.
+pub fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut word = String::new();
+        match chars.peek() {
+            None => return Ok(words),
+            Some('"') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ '"') | Some(c @ '\\') => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(String::from("Unterminated quote")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(String::from("Unterminated quote")),
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(c) = chars.peek().copied() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+            }
+        }
+        words.push(word);
+    }
+}
+
+/// Scores `candidate` for a fuzzy match of `pattern`: every pattern char
+/// must appear in order, and runs and word starts score higher.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<usize> {
+    let candidate = candidate.chars().collect::<Vec<char>>();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for p in pattern.chars() {
+        let found = (next..candidate.len()).find(|i| candidate[*i].eq_ignore_ascii_case(&p))?;
+        score += if previous.is_some_and(|i| i + 1 == found) {
+            3
+        } else if found == 0 || candidate[found - 1] == '-' {
+            2
+        } else {
+            1
+        };
+        previous = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
+
+/// Sorts `candidates` by how well they fuzzily match `pattern`, dropping
+/// those that don't match at all.
+fn fuzzy_filter(pattern: &str, candidates: Vec<String>) -> Vec<String> {
+    let mut scored = candidates
+        .into_iter()
+        .filter_map(|candidate| Some((fuzzy_score(pattern, &candidate)?, candidate)))
+        .collect::<Vec<(usize, String)>>();
+    scored.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .cmp(a_score)
+            .then(a.len().cmp(&b.len()))
+            .then(a.cmp(b))
+    });
+    scored.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+/// Tab completion for the command line: command names first, then the
+/// arguments the command expects.
+pub fn complete(input: &str) -> Vec<String> {
+    let name_end = match input.find(' ') {
+        Some(at) => at,
+        None => {
+            let names = COMMANDS.iter().map(|c| format!("{} ", c.name)).collect();
+            return fuzzy_filter(input, names);
+        }
+    };
+    let name = &input[..name_end];
+    let arg = input[name_end..].trim_start();
+    let command = match find(name) {
+        Some(command) => command,
+        None => return vec![],
+    };
+    let candidates = match command.name {
+        "save" => complete_path(arg),
+        "set" if !arg.contains(' ') => {
+            let options = OPTIONS.iter().map(|o| format!("{} ", o)).collect();
+            fuzzy_filter(arg, options)
+        }
+        "filetype" => {
+            let mut names = FileType::all()
+                .iter()
+                .map(|t| t.to_string().to_lowercase())
+                .collect::<Vec<String>>();
+            names.push(String::from("none"));
+            fuzzy_filter(arg, names)
+        }
+        "sort" => {
+            // the options combine, so only the last word is completed.
+            let (done, last) = arg.split_at(arg.rfind(' ').map_or(0, |at| at + 1));
+            let options = SORT_OPTIONS.iter().map(|o| o.to_string()).collect();
+            fuzzy_filter(last, options)
+                .into_iter()
+                .map(|option| format!("{}{}", done, option))
+                .collect()
+        }
+        _ => vec![],
+    };
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{} {}", name, candidate))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let (command, args) = parse("replace \"a b\" c").unwrap();
+        assert_eq!(command.name, "replace");
+        assert_eq!(args, vec!["a b", "c"]);
+        let (command, args) = parse("42").unwrap();
+        assert_eq!(command.name, "goto");
+        assert_eq!(args, vec!["42"]);
+        assert_eq!(parse("w").unwrap().0.name, "save");
+
+        assert!(parse("").is_err());
+        assert!(parse("frobnicate").is_err());
+        assert_eq!(
+            parse("replace a").err(),
+            Some(String::from("Usage: replace <from> <to>"))
+        );
+        assert!(parse("find \"oops").is_err());
+        let (command, args) = parse("!jq  '.a | \"b\"'").unwrap();
+        assert_eq!(command.name, "filter");
+        assert_eq!(args, vec!["jq  '.a | \"b\"'"]);
+        assert_eq!(parse("filter").unwrap().1, Vec::<String>::new());
+        assert_eq!(
+            split_args(r#"say "\"hi\" \n""#).unwrap(),
+            vec!["say", "\"hi\" \\n"]
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_complete() {
+        assert_eq!(fuzzy_score("fp", "find-previous"), Some(4));
+        assert_eq!(fuzzy_score("xyz", "find"), None);
+        assert_eq!(
+            complete("fn"),
+            vec!["find ", "find-next ", "find-previous "]
+        );
+        assert_eq!(complete("sort"), vec!["sort "]);
+        // the best matches come first.
+        assert_eq!(complete("srt")[..2], ["sort ", "line-start "]);
+        assert_eq!(complete("set tabw"), vec!["set tabwidth "]);
+        assert_eq!(complete("set sw"), vec!["set shiftwidth "]);
+        assert_eq!(complete("filetype rs"), vec!["filetype rust"]);
+        assert!(complete("quit x").is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::indent::leading_whitespace;
+use crate::syntax::EditorSyntax;
+
+/// The markers `toggle_comments` uses for `syntax`: its line comment, or
+/// its block comment when it has none.
+pub fn comment_markers(syntax: &EditorSyntax) -> Option<(String, String)> {
+    if let Some(start) = &syntax.singleline_comment_start {
+        return Some((start.clone(), String::new()));
+    }
+    if syntax.multiline_comment_start.is_empty() {
+        return None;
+    }
+    Some((
+        syntax.multiline_comment_start.clone(),
+        syntax.multiline_comment_end.clone(),
+    ))
+}
+
+/// Comments out `lines` with `start` (and `end`, for block comments), or
+/// uncomments them if every non-blank line is already commented. Markers
+/// go in at the smallest indentation so the lines stay aligned; blank
+/// lines are left alone.
+pub fn toggle_comments(lines: &[Vec<char>], start: &str, end: &str) -> Vec<Vec<char>> {
+    let start = start.chars().collect::<Vec<char>>();
+    let end = end.chars().collect::<Vec<char>>();
+    let is_blank = |line: &Vec<char>| line.iter().all(|c| c.is_whitespace());
+    let is_commented = |line: &Vec<char>| {
+        let body = &line[leading_whitespace(line).len()..];
+        body.starts_with(&start) && body.len() >= start.len() + end.len() && body.ends_with(&end)
+    };
+    if lines.iter().filter(|l| !is_blank(l)).all(is_commented) {
+        return lines
+            .iter()
+            .map(|line| {
+                if is_blank(line) {
+                    line.clone()
+                } else {
+                    uncomment(line, &start, &end)
+                }
+            })
+            .collect();
+    }
+    let column = lines
+        .iter()
+        .filter(|l| !is_blank(l))
+        .map(|line| leading_whitespace(line).len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            if is_blank(line) {
+                return line.clone();
+            }
+            let mut commented = line[..column].to_vec();
+            commented.extend(&start);
+            commented.push(' ');
+            commented.extend(&line[column..]);
+            if !end.is_empty() {
+                commented.push(' ');
+                commented.extend(&end);
+            }
+            commented
+        })
+        .collect()
+}
+
+// drops the markers along with the space next to each, if there is one.
+fn uncomment(line: &[char], start: &[char], end: &[char]) -> Vec<char> {
+    let indent = leading_whitespace(line);
+    let mut body = &line[indent.len() + start.len()..line.len() - end.len()];
+    if body.first() == Some(&' ') {
+        body = &body[1..];
+    }
+    if !end.is_empty() && body.last() == Some(&' ') {
+        body = &body[..body.len() - 1];
+    }
+    [indent, body].concat()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::FileType;
+
+    fn toggle(lines: &[&str], start: &str, end: &str) -> Vec<String> {
+        let lines = lines
+            .iter()
+            .map(|line| line.chars().collect())
+            .collect::<Vec<Vec<char>>>();
+        toggle_comments(&lines, start, end)
+            .iter()
+            .map(|line| line.iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_toggle_line_comments() {
+        let code = ["    if x {", "        y();", "", "    }"];
+        let commented = toggle(&code, "//", "");
+        assert_eq!(
+            commented,
+            vec!["    // if x {", "    //     y();", "", "    // }"]
+        );
+        let commented = commented.iter().map(String::as_str).collect::<Vec<&str>>();
+        assert_eq!(toggle(&commented, "//", ""), code);
+
+        // a mix of commented and plain lines gets commented.
+        assert_eq!(toggle(&["# a", "b"], "#", ""), vec!["# # a", "# b"]);
+        assert_eq!(toggle(&["#a"], "#", ""), vec!["a"]);
+    }
+
+    #[test]
+    fn test_toggle_block_comments() {
+        assert_eq!(toggle(&["  int x;"], "/*", "*/"), vec!["  /* int x; */"]);
+        assert_eq!(toggle(&["  /* int x; */"], "/*", "*/"), vec!["  int x;"]);
+        assert_eq!(toggle(&["/**/"], "/*", "*/"), vec![""]);
+    }
+
+    #[test]
+    fn test_comment_markers() {
+        assert_eq!(
+            comment_markers(&EditorSyntax::new(FileType::Rust)),
+            Some((String::from("//"), String::new()))
+        );
+        // a filetype with only block comments, like CSS.
+        let css = EditorSyntax {
+            singleline_comment_start: None,
+            ..EditorSyntax::new(FileType::C)
+        };
+        let (start, end) = comment_markers(&css).unwrap();
+        assert_eq!((start.as_str(), end.as_str()), ("/*", "*/"));
+        assert_eq!(toggle(&["a {}"], &start, &end), vec!["/* a {} */"]);
+        let none = EditorSyntax {
+            singleline_comment_start: None,
+            ..EditorSyntax::new(FileType::Python)
+        };
+        assert_eq!(comment_markers(&none), None);
+    }
+}
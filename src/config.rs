@@ -0,0 +1,124 @@
+use crate::syntax::FileType;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const APP_NAME: &str = "rust-editor";
+const CONFIG_FILE_NAME: &str = "config";
+
+/// `$XDG_CONFIG_HOME/rust-editor`, falling back to `~/.config/rust-editor`.
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_STATE_HOME/rust-editor`, falling back to `~/.local/state/rust-editor`.
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+fn xdg_dir(variable: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(variable) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(fallback),
+    };
+    Some(base.join(APP_NAME))
+}
+
+/// Splits `key = value` lines, skipping blanks and `#` comments. Each entry
+/// keeps its 1-based line number so errors can point at it.
+pub fn parse_key_values(text: &str) -> Result<Vec<(usize, String, String)>, String> {
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.find('=') {
+            Some(at) => entries.push((
+                i + 1,
+                line[..at].trim().to_string(),
+                line[at + 1..].trim().to_string(),
+            )),
+            None => return Err(format!("line {}: expected `key = value`", i + 1)),
+        }
+    }
+    Ok(entries)
+}
+
+pub struct Config {
+    pub theme: String,
+    /// `default`, `vim` or `emacs`.
+    pub editing_mode: String,
+    pub auto_pairs: bool,
+    /// the command each filetype is run through before saving.
+    pub formatters: Vec<(FileType, String)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: String::from("default"),
+            editing_mode: String::from("default"),
+            auto_pairs: true,
+            formatters: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Reads `config` from the config directory. A missing file is not an
+    /// error; anything that can't be understood is reported and skipped.
+    pub fn load() -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let path = match config_dir() {
+            Some(dir) => dir.join(CONFIG_FILE_NAME),
+            None => return (config, vec![]),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return (config, vec![]),
+        };
+        let errors = config.apply(&text);
+        (config, errors)
+    }
+
+    fn apply(&mut self, text: &str) -> Vec<String> {
+        let entries = match parse_key_values(text) {
+            Ok(entries) => entries,
+            Err(e) => return vec![format!("config: {}", e)],
+        };
+        let mut errors = vec![];
+        for (line, key, value) in entries {
+            match key.as_str() {
+                "theme" => self.theme = value,
+                "editing" => match value.as_str() {
+                    "default" | "vim" | "emacs" => self.editing_mode = value,
+                    _ => errors.push(format!(
+                        "config line {}: unknown editing mode `{}`",
+                        line, value
+                    )),
+                },
+                "autopairs" => match value.as_str() {
+                    "on" | "off" => self.auto_pairs = value == "on",
+                    _ => errors.push(format!(
+                        "config line {}: autopairs must be on or off, not `{}`",
+                        line, value
+                    )),
+                },
+                _ => match key.strip_prefix("formatter.") {
+                    Some(name) => match FileType::from_name(name) {
+                        Some(file_type) => {
+                            self.formatters.retain(|(t, _)| *t != file_type);
+                            self.formatters.push((file_type, value));
+                        }
+                        None => errors
+                            .push(format!("config line {}: unknown filetype `{}`", line, name)),
+                    },
+                    None => errors.push(format!("config line {}: unknown option `{}`", line, key)),
+                },
+            }
+        }
+        errors
+    }
+}
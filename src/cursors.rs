@@ -0,0 +1,108 @@
+use crate::Row;
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The start and end of the word under, or else just before, `x`.
+pub fn word_at(buf: &[char], x: usize) -> Option<(usize, usize)> {
+    let x = if buf.get(x).is_some_and(|c| is_word_char(*c)) {
+        x
+    } else if x > 0 && buf.get(x - 1).is_some_and(|c| is_word_char(*c)) {
+        x - 1
+    } else {
+        return None;
+    };
+    let start = buf[..x]
+        .iter()
+        .rposition(|c| !is_word_char(*c))
+        .map_or(0, |i| i + 1);
+    let end = buf[x..]
+        .iter()
+        .position(|c| !is_word_char(*c))
+        .map_or(buf.len(), |i| x + i);
+    Some((start, end))
+}
+
+/// Where the next whole-word `word` after `after` starts, wrapping around
+/// the end of `rows`.
+pub fn next_occurrence(
+    rows: &[Row],
+    word: &[char],
+    after: (usize, usize),
+) -> Option<(usize, usize)> {
+    let is_word_at = |buf: &[char], x: usize| {
+        buf[x..].starts_with(word)
+            && (x == 0 || !is_word_char(buf[x - 1]))
+            && buf.get(x + word.len()).is_none_or(|c| !is_word_char(*c))
+    };
+    // the row of `after` comes up again last, for matches before it.
+    for i in 0..=rows.len() {
+        let y = (after.1 + i) % rows.len().max(1);
+        let buf = &rows.get(y)?.buf;
+        let from = if i == 0 { after.0 + 1 } else { 0 };
+        let to = if i == rows.len() {
+            after.0 + 1
+        } else {
+            buf.len()
+        };
+        if let Some(x) = (from..to.min(buf.len())).find(|x| is_word_at(buf, *x)) {
+            return Some((x, y));
+        }
+    }
+    None
+}
+
+/// `at` counted back from the end of `rows`: rows from the last one and
+/// chars from the end of its row. Edits before `at` leave this the same.
+pub fn anchor(rows: &[Row], at: (usize, usize)) -> (usize, usize) {
+    let len = rows.get(at.1).map_or(0, |row| row.buf.len());
+    (rows.len().saturating_sub(at.1), len.saturating_sub(at.0))
+}
+
+/// The position `anchor` counts back to.
+pub fn unanchor(rows: &[Row], anchor: (usize, usize)) -> (usize, usize) {
+    let y = rows.len().saturating_sub(anchor.0);
+    let len = rows.get(y).map_or(0, |row| row.buf.len());
+    (len.saturating_sub(anchor.1), y)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rows(lines: &[&str]) -> Vec<Row> {
+        lines
+            .iter()
+            .map(|line| Row::from(&String::from(*line)))
+            .collect()
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        let rows = rows(&["let count = 0;", "count += counter;", "f(count)"]);
+        let word = "count".chars().collect::<Vec<char>>();
+        assert_eq!(word_at(&rows[0].buf, 6), Some((4, 9)));
+        assert_eq!(word_at(&rows[0].buf, 9), Some((4, 9)));
+        assert_eq!(word_at(&rows[0].buf, 10), None);
+
+        assert_eq!(next_occurrence(&rows, &word, (4, 0)), Some((0, 1)));
+        // `counter` is not a match.
+        assert_eq!(next_occurrence(&rows, &word, (0, 1)), Some((2, 2)));
+        assert_eq!(next_occurrence(&rows, &word, (2, 2)), Some((4, 0)));
+        assert_eq!(next_occurrence(&rows, &['x'], (0, 0)), None);
+    }
+
+    #[test]
+    fn test_anchor() {
+        let before = rows(&["ab", "cd"]);
+        let at = anchor(&before, (1, 1));
+        // a line break typed before the cursor.
+        let after = rows(&["a", "b", "cd"]);
+        assert_eq!(unanchor(&after, at), (1, 2));
+        // the two lines joined.
+        let after = rows(&["abcd"]);
+        assert_eq!(unanchor(&after, at), (3, 0));
+        assert_eq!(unanchor(&[], anchor(&[], (0, 0))), (0, 0));
+    }
+}
@@ -1,25 +1,44 @@
+use crate::undo::{Change, Step, UndoHistory};
 use crate::Highlight;
 use crate::Row;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 
+/// Rows `start..old_end` of the text as it was when edits were last taken
+/// have become rows `start..new_end`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EditRange {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
 #[derive(Default)]
 pub struct Document {
     pub rows: Vec<Row>,
+    pending_edit: Option<EditRange>,
+    history: UndoHistory,
 }
 impl Document {
     pub fn open(file_name: &str) -> Result<Document, std::io::Error> {
         let file = File::open(file_name)?;
 
         let mut editor_lines = vec![];
-        for line in BufReader::new(file).lines() {
-            match line {
-                Ok(s) => editor_lines.push(Row::from(&s)),
-                Err(_) => {}
+        for line in BufReader::new(file).split(b'\n') {
+            let mut line = line?;
+            if line.ends_with(b"\r") {
+                line.pop();
             }
+            // bytes that are not UTF-8 become U+FFFD rather than losing the
+            // line, or every line after it.
+            let s = String::from_utf8_lossy(&line).into_owned();
+            editor_lines.push(Row::from(&s));
         }
-        Ok(Document { rows: editor_lines })
+        Ok(Document {
+            rows: editor_lines,
+            ..Document::default()
+        })
     }
 
     pub fn row(&self, index: usize) -> Option<&Row> {
@@ -35,10 +54,231 @@ impl Document {
     }
 
     pub fn replace_buf(&mut self, index: usize, new_buf: Vec<char>) -> Result<(), std::io::Error> {
-        self.rows[index].buf = new_buf;
+        let undo = self.apply(Change::Replace {
+            index,
+            buf: new_buf,
+        });
+        self.history.record(undo);
         Ok(())
     }
 
+    pub fn insert_row(&mut self, index: usize, row: Row) {
+        let undo = self.apply(Change::Insert { index, row });
+        self.history.record(undo);
+    }
+
+    pub fn remove_row(&mut self, index: usize) -> Row {
+        let row = self.rows[index].clone();
+        let undo = self.apply(Change::Remove { index });
+        self.history.record(undo);
+        row
+    }
+
+    // makes `change` without recording it, returning its reverse.
+    fn apply(&mut self, change: Change) -> Change {
+        match change {
+            Change::Replace { index, buf } => {
+                let old = std::mem::replace(&mut self.rows[index].buf, buf);
+                self.record_edit(index, index + 1, index + 1);
+                Change::Replace { index, buf: old }
+            }
+            Change::Insert { index, row } => {
+                self.rows.insert(index, row);
+                self.record_edit(index, index, index + 1);
+                Change::Remove { index }
+            }
+            Change::Remove { index } => {
+                let row = self.rows.remove(index);
+                self.record_edit(index, index + 1, index);
+                Change::Insert { index, row }
+            }
+        }
+    }
+
+    /// Starts a new undo step with the next change; `cursor` is where undoing
+    /// it will put the cursor back.
+    pub fn begin_undo_step(&mut self, cursor: (usize, usize)) {
+        self.history.begin_step(cursor);
+    }
+
+    /// Makes everything up to `end_undo_group` one undo step.
+    pub fn begin_undo_group(&mut self, cursor: (usize, usize)) {
+        self.history.begin_group(cursor);
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Marks the document as saved; `cursor` is where undoing the next
+    /// change will put the cursor back.
+    pub fn mark_saved(&mut self, cursor: (usize, usize)) {
+        self.history.mark_saved(cursor);
+    }
+
+    /// Whether the document is as it was when last saved, as far as its undo
+    /// history knows.
+    pub fn is_saved(&self) -> bool {
+        self.history.is_saved()
+    }
+
+    /// Reverts the last step, returning the cursor from before it.
+    pub fn undo(&mut self, cursor: (usize, usize)) -> Option<(usize, usize)> {
+        let step = self.history.pop_undo()?;
+        let (redo, before) = self.revert(step, cursor);
+        self.history.push_redo(redo);
+        Some(before)
+    }
+
+    /// Makes the last undone step again, returning the cursor from after it.
+    pub fn redo(&mut self, cursor: (usize, usize)) -> Option<(usize, usize)> {
+        let step = self.history.pop_redo()?;
+        let (undo, after) = self.revert(step, cursor);
+        self.history.push_undo(undo);
+        Some(after)
+    }
+
+    // applies a step's changes, returning the step that reverses it.
+    fn revert(&mut self, step: Step, cursor: (usize, usize)) -> (Step, (usize, usize)) {
+        // the reverses come out last change first, which is the order that
+        // undoes them again when applied last to first.
+        let changes = step
+            .changes
+            .into_iter()
+            .rev()
+            .map(|change| self.apply(change))
+            .collect::<Vec<Change>>();
+        let id = step.id;
+        (
+            Step {
+                id,
+                changes,
+                cursor,
+            },
+            step.cursor,
+        )
+    }
+
+    /// The text from `start` up to `end`, both `(x, y)`, with rows joined by
+    /// `\n`. `x` may be the row length to take in the line break.
+    pub fn text_range(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let mut text = String::new();
+        for y in start.1..=end.1.min(self.rows.len().saturating_sub(1)) {
+            let buf = &self.rows[y].buf;
+            let from = if y == start.1 {
+                start.0.min(buf.len())
+            } else {
+                0
+            };
+            let to = if y == end.1 {
+                end.0.min(buf.len())
+            } else {
+                buf.len()
+            };
+            text.extend(buf[from..to.max(from)].iter());
+            if y != end.1 {
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Removes the text from `start` up to `end`, joining the rows it spans,
+    /// and returns it.
+    pub fn delete_range(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        if start.1 >= self.rows.len() || (start.1, start.0) >= (end.1, end.0) {
+            return String::new();
+        }
+        let end = if end.1 >= self.rows.len() {
+            let last = self.rows.len() - 1;
+            (self.rows[last].buf.len(), last)
+        } else {
+            end
+        };
+        let text = self.text_range(start, end);
+        let head = &self.rows[start.1].buf;
+        let mut buf = head[..start.0.min(head.len())].to_vec();
+        let tail = &self.rows[end.1].buf;
+        buf.extend_from_slice(&tail[end.0.min(tail.len())..]);
+        for y in (start.1 + 1..=end.1).rev() {
+            self.remove_row(y);
+        }
+        let _ = self.replace_buf(start.1, buf);
+        text
+    }
+
+    /// Removes rows `first..=last` along with a line break, leaving one
+    /// empty row if they were all there was.
+    pub fn delete_lines(&mut self, first: usize, last: usize) {
+        let row_len = |document: &Document, y: usize| document.rows[y].buf.len();
+        let (start, end) = if last + 1 < self.rows.len() {
+            ((0, first), (0, last + 1))
+        } else if first > 0 {
+            (
+                (row_len(self, first - 1), first - 1),
+                (row_len(self, last), last),
+            )
+        } else {
+            ((0, first), (row_len(self, last), last))
+        };
+        self.delete_range(start, end);
+    }
+
+    /// Inserts `text` at `(x, y)`, splitting rows at `\n`, and returns the
+    /// position just after it.
+    pub fn insert_text(&mut self, at: (usize, usize), text: &str) -> (usize, usize) {
+        let (x, y) = at;
+        if y >= self.rows.len() {
+            self.insert_row(self.rows.len(), Row::from(&String::new()));
+        }
+        let y = y.min(self.rows.len() - 1);
+        let buf = &self.rows[y].buf;
+        let x = x.min(buf.len());
+        let tail = buf[x..].to_vec();
+        let mut lines = text.split('\n');
+        let mut current = buf[..x].to_vec();
+        current.extend(lines.next().unwrap_or("").chars());
+        let mut y_end = y;
+        for line in lines {
+            let _ = self.replace_buf(y_end, current);
+            y_end += 1;
+            self.insert_row(y_end, Row::from(&String::new()));
+            current = line.chars().collect();
+        }
+        let x_end = current.len();
+        current.extend(tail);
+        let _ = self.replace_buf(y_end, current);
+        (x_end, y_end)
+    }
+
+    /// Returns the rows changed since the last call, merged into one range.
+    pub fn take_edit(&mut self) -> Option<EditRange> {
+        self.pending_edit.take()
+    }
+
+    fn record_edit(&mut self, start: usize, old_end: usize, new_end: usize) {
+        let edit = EditRange {
+            start,
+            old_end,
+            new_end,
+        };
+        self.pending_edit = Some(match self.pending_edit {
+            None => edit,
+            Some(pending) => {
+                // rows at the bottom that neither edit touched stay lined up.
+                let len_before_edit = self.rows.len() + old_end - new_end;
+                let original_len = len_before_edit + pending.old_end - pending.new_end;
+                let untouched_suffix =
+                    std::cmp::min(original_len - pending.old_end, len_before_edit - old_end);
+                EditRange {
+                    start: std::cmp::min(pending.start, start),
+                    old_end: original_len - untouched_suffix,
+                    new_end: self.rows.len() - untouched_suffix,
+                }
+            }
+        });
+    }
+
     pub fn replace_render(
         &mut self,
         index: usize,
@@ -56,14 +296,132 @@ impl Document {
         self.rows[index].highlight = new_highlight;
         Ok(())
     }
+}
 
-    pub fn replace_char_highlight(
-        &mut self,
-        row_index: usize,
-        char_index: usize,
-        new_highlight: Highlight,
-    ) -> Result<(), std::io::Error> {
-        self.rows[row_index].highlight[char_index] = new_highlight;
-        Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn document(lines: &[&str]) -> Document {
+        let mut document = Document::default();
+        for (i, line) in lines.iter().enumerate() {
+            document.insert_row(i, Row::from(&String::from(*line)));
+        }
+        document
+    }
+
+    fn lines(document: &Document) -> Vec<String> {
+        document
+            .rows
+            .iter()
+            .map(|row| row.buf.iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_open_invalid_utf8() {
+        let path = std::env::temp_dir().join(format!("rust-editor-{}.txt", std::process::id()));
+        std::fs::write(&path, b"one\r\n\xff\xfe bad\nthree\nfour\n").unwrap();
+        let doc = Document::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            lines(&doc.unwrap()),
+            vec!["one", "\u{fffd}\u{fffd} bad", "three", "four"]
+        );
+    }
+
+    #[test]
+    fn test_text_ranges() {
+        let mut doc = document(&["hello", "big", "world"]);
+        assert_eq!(doc.text_range((1, 0), (3, 0)), "el");
+        assert_eq!(doc.text_range((3, 0), (2, 2)), "lo\nbig\nwo");
+        assert_eq!(doc.text_range((0, 1), (3, 1)), "big");
+
+        assert_eq!(doc.delete_range((3, 0), (2, 2)), "lo\nbig\nwo");
+        assert_eq!(lines(&doc), vec!["helrld"]);
+        assert_eq!(doc.delete_range((2, 0), (2, 0)), "");
+
+        assert_eq!(doc.insert_text((3, 0), "lo\nbig\nwo"), (2, 2));
+        assert_eq!(lines(&doc), vec!["hello", "big", "world"]);
+        assert_eq!(doc.insert_text((5, 0), "!"), (6, 0));
+        assert_eq!(doc.delete_range((6, 0), (0, 1)), "\n");
+        assert_eq!(lines(&doc), vec!["hello!big", "world"]);
+
+        let mut empty = Document::default();
+        assert_eq!(empty.insert_text((0, 0), "a\n"), (0, 1));
+        assert_eq!(lines(&empty), vec!["a", ""]);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut doc = document(&["one", "two"]);
+        doc.begin_undo_step((1, 0));
+        doc.insert_text((3, 0), "!\nnew");
+        doc.begin_undo_step((0, 1));
+        doc.remove_row(0);
+        assert_eq!(lines(&doc), vec!["new", "two"]);
+
+        assert_eq!(doc.undo((0, 0)), Some((0, 1)));
+        assert_eq!(lines(&doc), vec!["one!", "new", "two"]);
+        assert_eq!(doc.undo((0, 1)), Some((1, 0)));
+        assert_eq!(lines(&doc), vec!["one", "two"]);
+
+        assert_eq!(doc.redo((1, 0)), Some((0, 1)));
+        assert_eq!(lines(&doc), vec!["one!", "new", "two"]);
+        // a new change forgets what could be redone.
+        doc.begin_undo_step((0, 0));
+        let _ = doc.replace_buf(2, vec!['x']);
+        assert_eq!(doc.redo((0, 0)), None);
+        assert_eq!(doc.undo((0, 2)), Some((0, 0)));
+        assert_eq!(lines(&doc), vec!["one!", "new", "two"]);
+
+        // steps begun inside a group are undone with it.
+        doc.begin_undo_group((2, 1));
+        doc.remove_row(0);
+        doc.begin_undo_step((0, 0));
+        doc.remove_row(0);
+        doc.end_undo_group();
+        assert_eq!(lines(&doc), vec!["two"]);
+        assert_eq!(doc.undo((0, 0)), Some((2, 1)));
+        assert_eq!(lines(&doc), vec!["one!", "new", "two"]);
+    }
+
+    #[test]
+    fn test_change_after_undo_is_its_own_step() {
+        let mut doc = document(&["one"]);
+        doc.begin_undo_step((0, 0));
+        let _ = doc.replace_buf(0, vec!['a']);
+        doc.begin_undo_step((0, 0));
+        let _ = doc.replace_buf(0, vec!['b']);
+        doc.undo((0, 0));
+        // no `begin_undo_step`, yet this doesn't join the step making `a`.
+        let _ = doc.replace_buf(0, vec!['c']);
+        doc.undo((0, 0));
+        assert_eq!(lines(&doc), vec!["a"]);
+
+        doc.redo((0, 0));
+        let _ = doc.replace_buf(0, vec!['d']);
+        doc.undo((0, 0));
+        assert_eq!(lines(&doc), vec!["c"]);
+    }
+
+    #[test]
+    fn test_saved_state() {
+        let mut doc = document(&["one"]);
+        doc.mark_saved((0, 0));
+        assert!(doc.is_saved());
+        let _ = doc.replace_buf(0, vec!['x']);
+        assert!(!doc.is_saved());
+        doc.undo((0, 0));
+        assert!(doc.is_saved());
+        doc.redo((0, 0));
+        assert!(!doc.is_saved());
+
+        // a change after saving is a step of its own.
+        doc.mark_saved((0, 0));
+        let _ = doc.replace_buf(0, vec!['y']);
+        doc.undo((0, 0));
+        assert_eq!(lines(&doc), vec!["x"]);
+        assert!(doc.is_saved());
     }
 }
@@ -1,31 +1,71 @@
+use crate::brackets::{find_match, is_bracket};
+use crate::command;
+use crate::comment::{comment_markers, toggle_comments};
+use crate::config::Config;
+use crate::cursors::{anchor, next_occurrence, unanchor, word_at};
+use crate::filter;
+use crate::format::moved_cursor;
+use crate::highlighter::{highlight_line, LineState};
+use crate::history::History;
+use crate::indent::{
+    backspace_width, break_line, dedent, leading_whitespace, shift, soft_tab, IndentStyle,
+};
+use crate::keymap::{keys_to_string, Keymap, Lookup};
+use crate::macros::{register_name, Macros, DEFAULT_REGISTER};
+use crate::pairs::{closer, in_empty_pair, pair_for, types_over};
+use crate::prompt::{complete_path, Completer, LineInput};
+use crate::rectangle::{column_range, extract, insert_at_column};
+use crate::registers::{Register, Registers};
+use crate::syntax::{detect_file_type, EditorSyntax, FileType};
+use crate::theme::{ColorSupport, Style, Theme, UiElement};
+use crate::transform::{
+    change_case, retab, sort_lines, trim_trailing_whitespace, unique_lines, Case, SortOptions,
+};
+#[cfg(feature = "tree-sitter-highlight")]
+use crate::treesitter::TreeSitterHighlighter;
+use crate::vim::{
+    apply_motion, clamp_to_char, Action, InsertAt, Mode, Motion, Operator, Parsed, Parser, Vim,
+};
 use crate::Document;
 use crate::Highlight;
 use crate::Row;
 use crate::Terminal;
 
 use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
 use std::env;
-use std::ffi::OsStr;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{stdin, stdout, Write};
-use std::path::Path;
 use termion::event::Key;
 use termion::*;
 
-use std::fmt::{Display, Formatter, Result as FormatResult};
-
 const KILO_VERSION: &str = "1.0";
 const KILL_TAB_STOP: u8 = 4;
 const QUIT_TIMES: u8 = 1; // 1 for dev.
-
-const KEY_WORD_1: [&str; 15] = [
-    "switch", "if", "while", "for", "break", "continue", "return", "else", "struct", "union",
-    "typedef", "static", "enum", "class", "case",
-];
-
-const KEY_WORD_2: [&str; 8] = [
-    "int", "long", "double", "float", "char", "unsigned", "signed", "void",
+const SEARCH_HISTORY_FILE_NAME: &str = "search_history";
+const COMMAND_HISTORY_FILE_NAME: &str = "command_history";
+// how many clipboard entries the picker lists, and how much of each.
+const PICKER_ENTRIES: usize = 9;
+const PREVIEW_CHARS: usize = 12;
+// commands that keep the other cursors; the rest only know about the
+// primary one and drop them.
+const MULTI_CURSOR_COMMANDS: &[&str] = &[
+    "save",
+    "quit",
+    "command-line",
+    "set",
+    "filetype",
+    "move-left",
+    "move-right",
+    "move-up",
+    "move-down",
+    "line-start",
+    "line-end",
+    "delete-backward",
+    "add-cursor-above",
+    "add-cursor-below",
+    "add-next-occurrence",
 ];
 
 #[derive(Debug)]
@@ -36,37 +76,122 @@ pub enum IncrementFindDirection {
 
 #[derive(Debug)]
 pub struct IncrementFind {
-    last_mached_row: Option<i16>,
-    direction: IncrementFindDirection,
+    query: String,
+    // every match as (row, render start, render end), in document order.
+    matches: Vec<(usize, usize, usize)>,
+    current: Option<usize>,
+    // where the cursor was when the search started.
+    origin: (usize, usize),
 }
 
 impl IncrementFind {
     fn new() -> Self {
         Self {
-            last_mached_row: None,
-            direction: IncrementFindDirection::Forward,
+            query: String::new(),
+            matches: vec![],
+            current: None,
+            origin: (0, 0),
+        }
+    }
+
+    /// Collects every match of `query` and makes the first one at or after
+    /// the origin current.
+    fn search(&mut self, query: &str, rows: &[Row]) {
+        self.query = String::from(query);
+        self.matches = vec![];
+        if !query.is_empty() {
+            for (y, row) in rows.iter().enumerate() {
+                for (start, end) in find_all(&row.render_string(), query) {
+                    self.matches.push((y, start, end));
+                }
+            }
+        }
+        self.current = if self.matches.is_empty() {
+            None
+        } else {
+            let origin = self.origin;
+            let next = self.matches.iter().position(|m| (m.0, m.1) >= origin);
+            Some(next.unwrap_or(0))
+        };
+    }
+
+    fn step(&mut self, direction: IncrementFindDirection) {
+        let len = self.matches.len();
+        self.current = self.current.map(|i| match direction {
+            IncrementFindDirection::Forward => (i + 1) % len,
+            IncrementFindDirection::Backward => (i + len - 1) % len,
+        });
+    }
+
+    fn current_match(&self) -> Option<(usize, usize, usize)> {
+        self.current.map(|i| self.matches[i])
+    }
+
+    /// Whether the render position is inside a match, and if so whether
+    /// that match is the current one.
+    fn match_at(&self, y: usize, x: usize) -> Option<bool> {
+        let first = self.matches.partition_point(|m| (m.0, m.2) <= (y, x));
+        match self.matches.get(first) {
+            Some(m) if m.0 == y && m.1 <= x => Some(self.current == Some(first)),
+            _ => None,
+        }
+    }
+
+    fn summary(&self) -> Option<String> {
+        if self.query.is_empty() {
+            return None;
+        }
+        match self.current {
+            Some(i) => Some(format!("match {} of {}", i + 1, self.matches.len())),
+            None => Some(String::from("no matches")),
         }
     }
 }
 
-struct EditorSyntax {
-    file_type: FileType,
-    singleline_comment_start: String,
-    multiline_comment_start: String,
-    multiline_comment_end: String,
-    highlight_number: bool,
-    highlight_strings: bool,
+/// `(row, render column)` of buffer position `(x, y)`, the way
+/// `IncrementFind` keeps its matches.
+fn render_position(rows: &[Row], (x, y): (usize, usize), tab_stop: usize) -> (usize, usize) {
+    let column = rows.get(y).map_or(0, |row| row.render_index(x, tab_stop));
+    (y, column)
 }
 
-enum FileType {
-    C,
+/// The buffer position `(x, y)` of `(row, render column)`, the inverse of
+/// `render_position`.
+fn buffer_position(rows: &[Row], (y, column): (usize, usize), tab_stop: usize) -> (usize, usize) {
+    let x = rows.get(y).map_or(0, |row| row.buf_index(column, tab_stop));
+    (x, y)
 }
 
-impl Display for FileType {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
-        match self {
-            FileType::C => write!(f, "C"),
-        }
+/// `a` and `b`, both `(x, y)`, in document order.
+fn ordered(a: (usize, usize), b: (usize, usize)) -> ((usize, usize), (usize, usize)) {
+    if (a.1, a.0) <= (b.1, b.0) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Char ranges of the non-overlapping occurrences of `query` in `line`.
+fn find_all(line: &str, query: &str) -> Vec<(usize, usize)> {
+    let query_len = query.chars().count();
+    let mut ranges = vec![];
+    let mut chars_before = 0;
+    let mut rest = line;
+    while let Some(at) = rest.find(query) {
+        let start = chars_before + rest[..at].chars().count();
+        ranges.push((start, start + query_len));
+        chars_before = start + query_len;
+        rest = &rest[at + query.len()..];
+    }
+    ranges
+}
+
+// a short one-line version of clipboard text for the history picker.
+fn preview(text: &str) -> String {
+    let line = text.replace('\n', "↵");
+    match line.char_indices().nth(PREVIEW_CHARS) {
+        Some((at, _)) => format!("{}…", &line[..at]),
+        None => line,
     }
 }
 
@@ -84,86 +209,1137 @@ pub struct Position {
 
 pub struct Editor {
     terminal: Terminal,
+    /// the primary cursor.
     position: Position,
+    /// the other cursors as `(x, y)`, when there are several.
+    cursors: Vec<(usize, usize)>,
     offset: Position,
     document: Document,
     file_name: Option<String>,
     editor_syntax: Option<EditorSyntax>,
+    #[cfg(feature = "tree-sitter-highlight")]
+    tree_sitter: Option<TreeSitterHighlighter>,
+    theme: Theme,
+    color_support: ColorSupport,
     status_message: String,
     status_message_time: DateTime<Utc>,
     is_dirty: bool,
     quit_times: u8,
     should_quit: bool,
     increment_find: IncrementFind,
+    search_history: History,
+    command_history: History,
+    tab_stop: usize,
+    indent_style: IndentStyle,
+    auto_pairs: bool,
+    formatters: Vec<(FileType, String)>,
+    keymap: Keymap,
+    // keys of a chord typed so far.
+    pending_keys: Vec<Key>,
+    // the modal layer, when Vim-style editing is on.
+    vim: Option<Vim>,
+    registers: Registers,
+    // the other end of the region, while one is marked.
+    mark: Option<(usize, usize)>,
+    // whether the region is the rectangle between the mark and the cursor
+    // rather than the text between them.
+    block: bool,
+    // the rectangle last copied or killed, a line per row.
+    rectangle: Vec<Vec<char>>,
+    // where the text put in by the last yank starts.
+    yank_start: Option<(usize, usize)>,
+    // the command run by the previous key, so kills can be joined and
+    // yanks replaced.
+    last_command: Option<&'static str>,
+    // column of the cursor on the message bar while a prompt is open.
+    prompt_cursor: Option<usize>,
+    macros: Macros,
+    // keys of the macro being played that have not been handled yet.
+    replay: VecDeque<Key>,
+    // registers of the macros being played, innermost last.
+    playing: Vec<char>,
+    // set when a macro fails, to stop every macro playing.
+    macro_aborted: bool,
 }
 
 impl Editor {
-    fn highlight_numbers(&self) -> bool {
-        match &self.editor_syntax {
-            Some(e_s) => return e_s.highlight_number,
-            None => return false,
+    fn new() -> Self {
+        let row_offset = 0;
+        let column_offset = 0;
+        let status_message = String::new();
+        let status_message_time = Utc::now();
+        let is_dirty = false;
+        let quit_times = QUIT_TIMES;
+        let should_quit = false;
+
+        Self {
+            position: Position::default(),
+            cursors: vec![],
+            offset: Position::default(),
+            terminal: Terminal::default(),
+            document: Document::default(),
+            file_name: None,
+            editor_syntax: None,
+            #[cfg(feature = "tree-sitter-highlight")]
+            tree_sitter: None,
+            theme: Theme::default(),
+            color_support: ColorSupport::detect(),
+            status_message,
+            status_message_time,
+            is_dirty,
+            quit_times,
+            should_quit,
+            increment_find: IncrementFind::new(),
+            search_history: History::default(),
+            command_history: History::default(),
+            tab_stop: KILL_TAB_STOP as usize,
+            indent_style: IndentStyle {
+                tabs: false,
+                width: KILL_TAB_STOP as usize,
+            },
+            auto_pairs: true,
+            formatters: vec![],
+            keymap: Keymap::default(),
+            pending_keys: vec![],
+            vim: None,
+            registers: Registers::default(),
+            mark: None,
+            block: false,
+            rectangle: vec![],
+            yank_start: None,
+            last_command: None,
+            prompt_cursor: None,
+            macros: Macros::default(),
+            replay: VecDeque::new(),
+            playing: vec![],
+            macro_aborted: false,
         }
     }
 
-    fn highlight_strings(&self) -> bool {
-        match &self.editor_syntax {
-            Some(e_s) => return e_s.highlight_strings,
-            None => return false,
+    fn editor_select_syntax_hilight(&mut self) {
+        let file_type = detect_file_type(self.file_name.as_deref(), &self.document.rows);
+        self.editor_set_syntax(file_type);
+    }
+
+    fn editor_set_syntax(&mut self, file_type: Option<FileType>) {
+        self.editor_syntax = file_type.map(EditorSyntax::new);
+        #[cfg(feature = "tree-sitter-highlight")]
+        {
+            self.tree_sitter = file_type.and_then(TreeSitterHighlighter::new);
+        }
+    }
+
+    fn editor_set_file_type(&mut self) {
+        let name = self.editor_prompt(String::from("Filetype: "), None, None, |_, _, _, _| {});
+        if !name.is_empty() {
+            self.editor_apply_file_type(&name);
+        }
+    }
+
+    fn editor_apply_file_type(&mut self, name: &str) {
+        if name == "none" {
+            self.editor_set_syntax(None);
+            self.set_status_message(String::from("Filetype cleared"));
+            return;
+        }
+        match FileType::from_name(name) {
+            Some(file_type) => {
+                self.editor_set_syntax(Some(file_type));
+                self.set_status_message(format!("Filetype set to {}", file_type));
+            }
+            None => self.set_status_message(format!("Unknown filetype: {}", name)),
+        }
+    }
+
+    fn editor_command_line(&mut self) {
+        let mut history = std::mem::take(&mut self.command_history);
+        let line = self.editor_prompt(
+            String::from(":"),
+            Some(&mut history),
+            Some(command::complete),
+            |_, _, _, _| {},
+        );
+        self.command_history = history;
+        if !line.is_empty() {
+            self.editor_run_command(&line);
+        }
+    }
+
+    fn editor_run_command(&mut self, line: &str) {
+        let (command, args) = match command::parse(line) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                self.set_status_message(e);
+                return;
+            }
+        };
+        let arg = |i: usize| args.get(i).map(String::as_str);
+        let previous = self.last_command.replace(command.name);
+        if !MULTI_CURSOR_COMMANDS.contains(&command.name) {
+            self.cursors.clear();
+        }
+        if !matches!(command.name, "undo" | "redo") {
+            self.document
+                .begin_undo_step((self.position.x, self.position.y));
+        }
+        match command.name {
+            "save" => {
+                if let Some(file_name) = arg(0) {
+                    self.file_name = Some(String::from(file_name));
+                    self.editor_select_syntax_hilight();
+                }
+                self.editor_save();
+            }
+            "quit" => self.should_quit = true,
+            "move-left" => self.editor_each_cursor(|editor| editor.move_cursor(Key::Left)),
+            "move-right" => self.editor_each_cursor(|editor| editor.move_cursor(Key::Right)),
+            "move-up" => self.editor_each_cursor(|editor| editor.move_cursor(Key::Up)),
+            "move-down" => self.editor_each_cursor(|editor| editor.move_cursor(Key::Down)),
+            "delete-backward" => self.editor_each_cursor(Self::editor_delete_char),
+            "add-cursor-above" => self.editor_add_cursor(false),
+            "add-cursor-below" => self.editor_add_cursor(true),
+            "add-next-occurrence" => self.editor_add_next_occurrence(),
+            "toggle-comment" => self.editor_toggle_comment(),
+            "undo" => self.editor_undo(false),
+            "redo" => self.editor_undo(true),
+            "duplicate-line" => self.editor_duplicate_lines(),
+            "delete-line" => self.editor_delete_lines(),
+            "move-line-up" => self.editor_move_lines(false),
+            "move-line-down" => self.editor_move_lines(true),
+            "join-lines" => self.editor_join_lines(),
+            "match-bracket" => match self.matching_bracket() {
+                Some((_, (x, y))) => {
+                    self.position.x = x;
+                    self.position.y = y;
+                }
+                None => self.set_status_message(String::from("No matching bracket")),
+            },
+            "indent" => self.editor_indent(),
+            "dedent" => self.editor_shift_lines(false),
+            "line-start" => self.editor_each_cursor(|editor| editor.position.x = 0),
+            "line-end" => self.editor_each_cursor(|editor| {
+                editor.position.x = editor.get_current_row_buf_length()
+            }),
+            "kill-line" => self.editor_kill_line(previous == Some("kill-line")),
+            "kill-region" => self.editor_kill_region(true),
+            "copy-region" => self.editor_kill_region(false),
+            "yank" => self.editor_yank(false),
+            "yank-pop" if matches!(previous, Some("yank") | Some("yank-pop")) => {
+                self.editor_yank(true)
+            }
+            // like Emacs, M-y not after a yank picks from the whole ring.
+            "yank-pop" => self.editor_paste_history(None),
+            "paste-history" => match arg(0).map(str::parse::<usize>) {
+                None => self.editor_paste_history(None),
+                Some(Ok(n)) => self.editor_paste_history(Some(n)),
+                Some(Err(_)) => self.set_status_message(format!("Usage: {}", command.usage)),
+            },
+            "copy-to-register" => {
+                if let Some(name) = self.editor_register_arg(arg(0)) {
+                    self.editor_copy_to_register(name);
+                }
+            }
+            "insert-register" => {
+                if let Some(name) = self.editor_register_arg(arg(0)) {
+                    match self.registers.get(name).cloned() {
+                        Some(register) => self.editor_insert_register(&register),
+                        None => self.set_status_message(format!("Register {} is empty", name)),
+                    }
+                }
+            }
+            "set-mark" => {
+                self.mark = Some((self.position.x, self.position.y));
+                self.block = false;
+                self.set_status_message(String::from("Mark set"));
+            }
+            "rectangle-mark" => {
+                self.mark = Some((self.position.x, self.position.y));
+                self.block = true;
+                self.set_status_message(String::from("Rectangle mark set"));
+            }
+            "copy-rectangle" => self.editor_kill_rectangle(false),
+            "kill-rectangle" => self.editor_kill_rectangle(true),
+            "yank-rectangle" => self.editor_yank_rectangle(),
+            "string-rectangle" => match arg(0) {
+                Some(text) => self.editor_string_rectangle(text),
+                None => {
+                    let text = self.editor_prompt(
+                        String::from("String rectangle: "),
+                        None,
+                        None,
+                        |_, _, _, _| {},
+                    );
+                    self.editor_string_rectangle(&text);
+                }
+            },
+            "filter" => match arg(0) {
+                Some(command) => self.editor_filter(command),
+                None => {
+                    let command = self.editor_prompt(
+                        String::from("Filter through: "),
+                        None,
+                        None,
+                        |_, _, _, _| {},
+                    );
+                    if !command.is_empty() {
+                        self.editor_filter(&command);
+                    }
+                }
+            },
+            "cancel" => {
+                self.mark = None;
+                self.cursors.clear();
+                self.pending_keys.clear();
+                self.set_status_message(String::from("Quit"));
+            }
+            "record-macro" => match arg(0).map_or(Ok(DEFAULT_REGISTER), register_name) {
+                Ok(register) => self.editor_record_macro(register),
+                Err(e) => self.set_status_message(e),
+            },
+            "play-macro" => {
+                let register = match arg(0) {
+                    Some(name) => register_name(name),
+                    None => Ok(self.macros.last.unwrap_or(DEFAULT_REGISTER)),
+                };
+                let count = arg(1).map_or(Ok(1), str::parse::<usize>);
+                match (register, count) {
+                    (Ok(register), Ok(count)) => self.editor_play_macro(register, count),
+                    (Err(e), _) => self.set_status_message(e),
+                    (_, Err(_)) => self.set_status_message(format!("Usage: {}", command.usage)),
+                }
+            }
+            "play-macro-lines" => {
+                let register = match arg(0) {
+                    Some(name) => register_name(name),
+                    None => Ok(self.macros.last.unwrap_or(DEFAULT_REGISTER)),
+                };
+                match register {
+                    Ok(register) => self.editor_play_macro_lines(register),
+                    Err(e) => self.set_status_message(e),
+                }
+            }
+            "save-macros" => match self.macros.save() {
+                Ok(()) => self.set_status_message(String::from("Macros saved")),
+                Err(e) => self.set_status_message(format!("Can't save macros: {}", e)),
+            },
+            "command-line" => self.editor_command_line(),
+            "goto" => match arg(0) {
+                Some(line) => self.editor_goto(line),
+                None => self.editor_go_to_line(),
+            },
+            "set" => self.editor_set_option(&args[0], &args[1]),
+            "filetype" => match arg(0) {
+                Some(name) => self.editor_apply_file_type(name),
+                None => self.editor_set_file_type(),
+            },
+            "find" => self.editor_find(),
+            "find-next" => self.editor_find_again(IncrementFindDirection::Forward),
+            "find-previous" => self.editor_find_again(IncrementFindDirection::Backward),
+            // an empty pattern matches between every two chars.
+            "replace" if args[0].is_empty() => {
+                self.set_status_message(format!("Usage: {}", command.usage))
+            }
+            "replace" => self.editor_replace(&args[0], &args[1]),
+            "sort" => match SortOptions::parse(&args) {
+                Ok(options) => self.editor_transform_lines("Sorted", |mut lines| {
+                    sort_lines(&mut lines, options);
+                    lines
+                }),
+                Err(e) => self.set_status_message(e),
+            },
+            "uniq" => self.editor_transform_lines("Kept", unique_lines),
+            "upcase" => self.editor_change_case(Case::Upper),
+            "downcase" => self.editor_change_case(Case::Lower),
+            "titlecase" => self.editor_change_case(Case::Title),
+            "trim-whitespace" => self.editor_transform_lines("Trimmed", |lines| {
+                lines
+                    .iter()
+                    .map(|line| trim_trailing_whitespace(line))
+                    .collect()
+            }),
+            "tabs-to-spaces" | "spaces-to-tabs" => {
+                let (tabs, width) = (command.name == "spaces-to-tabs", self.tab_stop);
+                self.editor_transform_lines("Retabbed", |lines| {
+                    lines.iter().map(|line| retab(line, tabs, width)).collect()
+                });
+            }
+            _ => self.set_status_message(format!("Not implemented: {}", command.name)),
+        }
+    }
+
+    fn editor_set_option(&mut self, name: &str, value: &str) {
+        match name {
+            "tabwidth" => match value.parse::<usize>() {
+                Ok(width) if (1..=16).contains(&width) => {
+                    self.tab_stop = width;
+                    self.set_status_message(format!("tabwidth = {}", width));
+                }
+                _ => self.set_status_message(format!("Bad tabwidth: {}", value)),
+            },
+            "shiftwidth" => match value.parse::<usize>() {
+                Ok(width) if (1..=16).contains(&width) => {
+                    self.indent_style.width = width;
+                    self.set_status_message(format!("shiftwidth = {}", width));
+                }
+                _ => self.set_status_message(format!("Bad shiftwidth: {}", value)),
+            },
+            "autopairs" => match value {
+                "on" | "off" => {
+                    self.auto_pairs = value == "on";
+                    self.set_status_message(format!("autopairs = {}", value));
+                }
+                _ => self.set_status_message(format!("Bad autopairs: {} (on or off)", value)),
+            },
+            "expandtab" => match value {
+                "on" | "off" => {
+                    self.indent_style.tabs = value == "off";
+                    self.set_status_message(format!("expandtab = {}", value));
+                }
+                _ => self.set_status_message(format!("Bad expandtab: {} (on or off)", value)),
+            },
+            "editing" => match value {
+                "default" | "vim" | "emacs" => {
+                    let errors = self.editor_set_editing_mode(value);
+                    if errors.is_empty() {
+                        self.set_status_message(format!("editing = {}", value));
+                    } else {
+                        self.set_status_message(errors.join(" | "));
+                    }
+                }
+                _ => self.set_status_message(format!("Unknown editing mode: {}", value)),
+            },
+            _ => self.set_status_message(format!("Unknown option: {}", name)),
+        }
+    }
+
+    /// Switches to the key bindings and modal layer of `mode`, returning
+    /// any problems with the user's keymap.
+    fn editor_set_editing_mode(&mut self, mode: &str) -> Vec<String> {
+        let (keymap, errors) = Keymap::load(mode);
+        self.keymap = keymap;
+        self.pending_keys.clear();
+        self.mark = None;
+        self.vim = if mode == "vim" {
+            Some(Vim::default())
+        } else {
+            None
+        };
+        errors
+    }
+
+    /// Kills to the end of the line, or the line break when already there.
+    fn editor_kill_line(&mut self, append: bool) {
+        if self.document.is_empty() {
+            return;
+        }
+        let (x, y) = (self.position.x, self.position.y);
+        let len = self.get_current_row_buf_length();
+        let end = if x < len { (len, y) } else { (0, y + 1) };
+        let text = self.document.delete_range((x, y), end);
+        if !text.is_empty() {
+            self.is_dirty = true;
+        }
+        if let Err(e) = self.registers.kill(&text, append) {
+            self.set_status_message(e);
+        }
+    }
+
+    /// Kills or copies the text between the mark and the cursor.
+    fn editor_kill_region(&mut self, delete: bool) {
+        let mark = match self.mark.take() {
+            Some(mark) => mark,
+            None => {
+                self.set_status_message(String::from("The mark is not set"));
+                return;
+            }
+        };
+        let (start, end) = ordered(mark, (self.position.x, self.position.y));
+        let text = if delete {
+            self.is_dirty = true;
+            self.position.x = start.0;
+            self.position.y = start.1;
+            self.document.delete_range(start, end)
+        } else {
+            self.document.text_range(start, end)
+        };
+        if let Err(e) = self.registers.kill(&text, false) {
+            self.set_status_message(e);
+        }
+    }
+
+    /// Inserts the newest kill at the cursor. `pop` replaces the text the
+    /// last yank put in with the kill before it.
+    fn editor_yank(&mut self, pop: bool) {
+        if pop {
+            if let Some(start) = self.yank_start {
+                self.document
+                    .delete_range(start, (self.position.x, self.position.y));
+                self.position.x = start.0;
+                self.position.y = start.1;
+            }
+        }
+        let text = if pop {
+            self.registers.ring.yank_pop()
+        } else {
+            self.registers.ring.yank()
+        };
+        let text = match text {
+            Some(register) => register.text.clone(),
+            None => {
+                self.set_status_message(String::from("Kill ring is empty"));
+                return;
+            }
+        };
+        let start = (self.position.x, self.position.y);
+        let (x, y) = self.document.insert_text(start, &text);
+        self.position.x = x;
+        self.position.y = y;
+        self.yank_start = Some(start);
+        self.mark = None;
+        self.is_dirty = true;
+    }
+
+    /// The rows and render columns of the rectangle between the mark and
+    /// the cursor, when one is marked.
+    fn rectangle_bounds(&self) -> Option<((usize, usize), (usize, usize))> {
+        if !self.block {
+            return None;
+        }
+        let column = |(x, y): (usize, usize)| {
+            self.document
+                .row(y)
+                .map_or(0, |row| row.render_index(x, self.tab_stop))
+        };
+        let (mark, cursor) = (self.mark?, (self.position.x, self.position.y));
+        let (top, bottom) = (mark.1.min(cursor.1), mark.1.max(cursor.1));
+        let (left, right) = (
+            column(mark).min(column(cursor)),
+            column(mark).max(column(cursor)),
+        );
+        // the char under a Vim cursor is part of the selection.
+        let right = if self.vim.is_some() { right + 1 } else { right };
+        Some(((top, bottom), (left, right)))
+    }
+
+    /// Copies the marked rectangle, and with `delete` takes it out of the
+    /// text, leaving the cursor at its top left corner.
+    fn editor_kill_rectangle(&mut self, delete: bool) {
+        let ((top, bottom), (left, right)) = match self.rectangle_bounds() {
+            Some(bounds) => bounds,
+            None => return self.set_status_message(String::from("No rectangle is marked")),
+        };
+        self.rectangle = (top..=bottom)
+            .map(|y| extract(&self.document.rows[y].buf, left, right, self.tab_stop))
+            .collect();
+        if delete {
+            for y in top..=bottom {
+                let mut buf = self.document.rows[y].buf.clone();
+                let range = column_range(&buf, left, right, self.tab_stop);
+                if !range.is_empty() {
+                    buf.drain(range);
+                    let _ = self.document.replace_buf(y, buf);
+                    self.is_dirty = true;
+                }
+            }
+            let buf = &self.document.rows[top].buf;
+            self.position.x = column_range(buf, left, left, self.tab_stop).start;
+            self.position.y = top;
+        }
+        self.mark = None;
+    }
+
+    /// Inserts the last rectangle with its top left corner at the cursor,
+    /// adding lines at the end of the file when it runs past it.
+    fn editor_yank_rectangle(&mut self) {
+        if self.rectangle.is_empty() {
+            return self.set_status_message(String::from("No rectangle to yank"));
+        }
+        let (x, y) = (self.position.x, self.position.y);
+        if y == self.document.len() {
+            self.document.insert_row(y, Row::from_buf(vec![]));
+        }
+        let column = self.document.rows[y].render_index(x, self.tab_stop);
+        let lines = self.rectangle.clone();
+        for (i, text) in lines.iter().enumerate() {
+            if y + i == self.document.len() {
+                self.document.insert_row(y + i, Row::from_buf(vec![]));
+            }
+            let buf = &self.document.rows[y + i].buf;
+            let (buf, end) = insert_at_column(buf, column, text, self.tab_stop);
+            let _ = self.document.replace_buf(y + i, buf);
+            self.position.x = end;
+        }
+        self.position.y = y + lines.len() - 1;
+        self.mark = None;
+        self.is_dirty = true;
+    }
+
+    /// Replaces each line of the marked rectangle with `text`.
+    fn editor_string_rectangle(&mut self, text: &str) {
+        if self.rectangle_bounds().is_none() {
+            return self.set_status_message(String::from("No rectangle is marked"));
+        }
+        self.editor_rectangle_to_cursors(true);
+        for c in text.chars() {
+            self.editor_each_cursor(|editor| editor.editor_row_insert_char(c));
+        }
+        self.cursors.clear();
+    }
+
+    /// Puts a cursor on each line of the marked rectangle at its left edge,
+    /// padding short lines out to it. With `delete` the rectangle's text
+    /// goes first.
+    fn editor_rectangle_to_cursors(&mut self, delete: bool) {
+        let ((top, bottom), (left, right)) = match self.rectangle_bounds() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let right = if delete { right } else { left };
+        let mut cursors = vec![];
+        for y in top..=bottom {
+            let mut buf = self.document.rows[y].buf.clone();
+            let range = column_range(&buf, left, right, self.tab_stop);
+            buf.drain(range);
+            let (buf, x) = insert_at_column(&buf, left, &[], self.tab_stop);
+            if buf != self.document.rows[y].buf {
+                let _ = self.document.replace_buf(y, buf);
+                self.is_dirty = true;
+            }
+            cursors.push((x, y));
+        }
+        let primary = cursors.remove(self.position.y - top);
+        self.position.x = primary.0;
+        self.position.y = primary.1;
+        self.cursors = cursors;
+        self.mark = None;
+    }
+
+    /// The register named by `arg`, or else asked for.
+    fn editor_register_arg(&mut self, arg: Option<&str>) -> Option<char> {
+        let name = match arg {
+            Some(name) => String::from(name),
+            None => self.editor_prompt(String::from("Register: "), None, None, |_, _, _, _| {}),
+        };
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_alphanumeric() || c == '"' => Some(c),
+            (None, _) => None,
+            _ => {
+                self.set_status_message(format!("`{}` is not a register", name));
+                None
+            }
+        }
+    }
+
+    /// Copies the selection, or else the cursor's line, into register
+    /// `name`.
+    fn editor_copy_to_register(&mut self, name: char) {
+        if !name.is_ascii_alphabetic() {
+            return self.set_status_message(String::from("Only registers a to z can be set"));
+        }
+        let register = match self.selection() {
+            Some((start, end)) => Register {
+                text: self.document.text_range(start, end),
+                linewise: false,
+            },
+            None => match self.document.row(self.position.y) {
+                Some(row) => Register {
+                    text: row.buf.iter().collect(),
+                    linewise: true,
+                },
+                None => return,
+            },
+        };
+        self.end_selection();
+        match self.registers.store(Some(name), register) {
+            Ok(()) => self.set_status_message(format!("Copied to register {}", name)),
+            Err(e) => self.set_status_message(e),
+        }
+    }
+
+    /// Inserts `register` at the cursor, or above the cursor's line when it
+    /// holds whole lines.
+    fn editor_insert_register(&mut self, register: &Register) {
+        let (x, y) = (self.position.x, self.position.y);
+        if register.linewise {
+            self.document
+                .insert_text((0, y), &format!("{}\n", register.text));
+            self.position.x = 0;
+        } else {
+            let (x, y) = self.document.insert_text((x, y), &register.text);
+            self.position.x = x;
+            self.position.y = y;
+        }
+        self.end_selection();
+        self.is_dirty = true;
+    }
+
+    /// Pastes entry `n` of the clipboard history, counting the newest as
+    /// 1, or picks one from a list of the newest entries.
+    fn editor_paste_history(&mut self, n: Option<usize>) {
+        let n = match n {
+            Some(n) => n,
+            None => {
+                let choices = self
+                    .registers
+                    .ring
+                    .recent()
+                    .take(PICKER_ENTRIES)
+                    .enumerate()
+                    .map(|(i, register)| format!("{}:{}", i + 1, preview(&register.text)))
+                    .collect::<Vec<String>>();
+                if choices.is_empty() {
+                    return self.set_status_message(String::from("The clipboard history is empty"));
+                }
+                let input = self.editor_prompt(
+                    format!("Paste {} > ", choices.join(" ")),
+                    None,
+                    None,
+                    |_, _, _, _| {},
+                );
+                match input.trim().parse::<usize>() {
+                    Ok(n) => n,
+                    Err(_) if input.is_empty() => return,
+                    Err(_) => return self.set_status_message(format!("No entry `{}`", input)),
+                }
+            }
+        };
+        match n
+            .checked_sub(1)
+            .and_then(|i| self.registers.ring.get(i))
+            .cloned()
+        {
+            Some(register) => self.editor_insert_register(&register),
+            None => self.set_status_message(format!("No entry {} in the clipboard history", n)),
+        }
+    }
+
+    /// Tab: indents the line when the cursor is in its indentation or a
+    /// selection is active, otherwise inserts a soft tab.
+    fn editor_indent(&mut self) {
+        let in_indent = match self.document.row(self.position.y) {
+            Some(row) => row.buf[..self.position.x].iter().all(|c| c.is_whitespace()),
+            None => return,
+        };
+        if in_indent || self.selection().is_some() {
+            self.editor_shift_lines(true);
+        } else {
+            for c in soft_tab(self.position.x, self.indent_style) {
+                self.editor_row_insert_char(c);
+            }
+        }
+    }
+
+    /// The first and last row of the selection, or the cursor's row.
+    fn selected_rows(&self) -> (usize, usize) {
+        let y = self.position.y;
+        match self.selection() {
+            // a region ending at the start of a line leaves that line out.
+            Some((start, end)) if end.0 == 0 && end.1 > start.1 => (start.1, end.1 - 1),
+            Some((start, end)) => (start.1, end.1.min(self.document.len() - 1)),
+            None => (y, y),
+        }
+    }
+
+    /// Indents or dedents the selected lines, or the cursor's line.
+    fn editor_shift_lines(&mut self, right: bool) {
+        if self.document.is_empty() {
+            return;
+        }
+        let y = self.position.y;
+        let (first, last) = self.selected_rows();
+        for row in first..=last {
+            let buf = &self.document.rows[row].buf;
+            if right && buf.is_empty() && first != last {
+                continue;
+            }
+            let shifted = shift(buf, self.indent_style, right);
+            if row == y {
+                let change = shifted.len() as isize - buf.len() as isize;
+                self.position.x = (self.position.x as isize + change).max(0) as usize;
+            }
+            if shifted != *buf {
+                let _ = self.document.replace_buf(row, shifted);
+                self.is_dirty = true;
+            }
+        }
+    }
+
+    /// Undoes the last step, or with `redo` makes the last undone one again.
+    fn editor_undo(&mut self, redo: bool) {
+        let cursor = (self.position.x, self.position.y);
+        let moved = if redo {
+            self.document.redo(cursor)
+        } else {
+            self.document.undo(cursor)
+        };
+        let (x, y) = match moved {
+            Some(moved) => moved,
+            None if redo => return self.set_status_message(String::from("Nothing to redo")),
+            None => return self.set_status_message(String::from("Nothing to undo")),
+        };
+        self.position.y = y.min(self.document.len().saturating_sub(1));
+        let len = self
+            .document
+            .row(self.position.y)
+            .map_or(0, |row| row.buf.len());
+        self.position.x = x.min(len);
+        self.cursors.clear();
+        self.end_selection();
+        self.is_dirty = !self.document.is_saved();
+    }
+
+    /// Runs `edit` at every cursor, the last in the file first. Cursors it
+    /// has run at are kept as anchors from the end of the file, which the
+    /// edits before them leave alone; cursors that end up together merge.
+    fn editor_each_cursor(&mut self, mut edit: impl FnMut(&mut Self)) {
+        if self.cursors.is_empty() {
+            return edit(self);
+        }
+        let primary = (self.position.x, self.position.y);
+        let mut cursors = std::mem::take(&mut self.cursors);
+        cursors.push(primary);
+        cursors.sort_by_key(|(x, y)| (*y, *x));
+        cursors.dedup();
+        let mut anchors = vec![];
+        for (x, y) in cursors.into_iter().rev() {
+            self.position.x = x;
+            self.position.y = y;
+            edit(self);
+            let at = (self.position.x, self.position.y);
+            anchors.push((anchor(&self.document.rows, at), (x, y) == primary));
+        }
+        let mut cursors = vec![];
+        for (anchor, is_primary) in anchors {
+            let (x, y) = unanchor(&self.document.rows, anchor);
+            if is_primary {
+                self.position.x = x;
+                self.position.y = y;
+            } else {
+                cursors.push((x, y));
+            }
+        }
+        cursors.sort_by_key(|(x, y)| (*y, *x));
+        cursors.dedup();
+        cursors.retain(|at| *at != (self.position.x, self.position.y));
+        self.cursors = cursors;
+    }
+
+    /// Adds a cursor on the line above the topmost cursor, or below the
+    /// bottom one, in the primary cursor's column or at the end of a shorter
+    /// line.
+    fn editor_add_cursor(&mut self, below: bool) {
+        let rows = self
+            .cursors
+            .iter()
+            .map(|(_, y)| *y)
+            .chain([self.position.y]);
+        let y = if below {
+            rows.max().unwrap_or(0) + 1
+        } else {
+            match rows.min().unwrap_or(0).checked_sub(1) {
+                Some(y) => y,
+                None => return,
+            }
+        };
+        let len = match self.document.row(y) {
+            Some(row) => row.buf.len(),
+            None => return,
+        };
+        self.end_selection();
+        self.cursors.push((self.position.x.min(len), y));
+        self.set_status_message(format!("{} cursors", self.cursors.len() + 1));
+    }
+
+    /// Adds a cursor at the next whole-word match of the word under the
+    /// primary cursor, after the last cursor added, at the same place in
+    /// the word.
+    fn editor_add_next_occurrence(&mut self) {
+        let (x, y) = (self.position.x, self.position.y);
+        let buf = match self.document.row(y) {
+            Some(row) => &row.buf,
+            None => return,
+        };
+        let (start, end) = match word_at(buf, x) {
+            Some(word) => word,
+            None => return self.set_status_message(String::from("No word under the cursor")),
+        };
+        let word = buf[start..end].to_vec();
+        let (last_x, last_y) = *self.cursors.last().unwrap_or(&(x, y));
+        let last_start = last_x.saturating_sub(x - start);
+        let found = next_occurrence(&self.document.rows, &word, (last_start, last_y))
+            .map(|(found_x, found_y)| (found_x + x - start, found_y))
+            .filter(|at| *at != (x, y) && !self.cursors.contains(at));
+        match found {
+            Some(at) => {
+                self.end_selection();
+                self.cursors.push(at);
+                self.set_status_message(format!("{} cursors", self.cursors.len() + 1));
+            }
+            None => self.set_status_message(String::from("No more occurrences")),
+        }
+    }
+
+    /// Moves the selection along when its rows move by `by`.
+    fn move_selection_rows(&mut self, by: isize) {
+        let moved = |(x, y): (usize, usize)| (x, (y as isize + by).max(0) as usize);
+        self.mark = self.mark.map(moved);
+        if let Some(vim) = &mut self.vim {
+            vim.anchor = moved(vim.anchor);
+        }
+    }
+
+    fn end_selection(&mut self) {
+        self.mark = None;
+        if self
+            .vim
+            .as_ref()
+            .is_some_and(|vim| vim.mode == Mode::Visual)
+        {
+            self.vim_set_mode(Mode::Normal);
+        }
+    }
+
+    /// Copies the selected lines, or the cursor's line, below themselves and
+    /// moves to the copy.
+    fn editor_duplicate_lines(&mut self) {
+        if self.document.is_empty() {
+            return;
+        }
+        let (first, last) = self.selected_rows();
+        let end = (self.document.rows[last].buf.len(), last);
+        let text = self.document.text_range((0, first), end);
+        self.document.insert_text(end, &format!("\n{}", text));
+        let count = last - first + 1;
+        self.position.y += count;
+        self.move_selection_rows(count as isize);
+        self.is_dirty = true;
+    }
+
+    fn editor_delete_lines(&mut self) {
+        if self.document.is_empty() {
+            return;
+        }
+        let (first, last) = self.selected_rows();
+        self.document.delete_lines(first, last);
+        self.position.y = first.min(self.document.len() - 1);
+        self.position.x = self.position.x.min(self.get_current_row_buf_length());
+        self.end_selection();
+        self.is_dirty = true;
+    }
+
+    /// Swaps the selected lines, or the cursor's line, with the line above
+    /// or below them.
+    fn editor_move_lines(&mut self, down: bool) {
+        if self.document.is_empty() {
+            return;
+        }
+        let (first, last) = self.selected_rows();
+        if down {
+            if last + 1 >= self.document.len() {
+                return;
+            }
+            let row = self.document.remove_row(last + 1);
+            self.document.insert_row(first, row);
+            self.position.y += 1;
+            self.move_selection_rows(1);
+        } else {
+            if first == 0 {
+                return;
+            }
+            let row = self.document.remove_row(first - 1);
+            self.document.insert_row(last, row);
+            self.position.y -= 1;
+            self.move_selection_rows(-1);
+        }
+        self.is_dirty = true;
+    }
+
+    /// Joins the selected lines, or the cursor's line and the next, with
+    /// the indentation between them squeezed to a space.
+    fn editor_join_lines(&mut self) {
+        let (first, last) = self.selected_rows();
+        let last = last.max(first + 1);
+        if last >= self.document.len() {
+            return;
+        }
+        for _ in first..last {
+            let left = &self.document.rows[first].buf;
+            let right = &self.document.rows[first + 1].buf;
+            let indent = leading_whitespace(right).len();
+            let space = left.last().is_some_and(|c| !c.is_whitespace()) && right.len() > indent;
+            let join = left.len();
+            self.document
+                .delete_range((join, first), (indent, first + 1));
+            if space {
+                self.document.insert_text((join, first), " ");
+            }
+            self.position.x = join;
+        }
+        self.position.y = first;
+        self.end_selection();
+        self.is_dirty = true;
+    }
+
+    /// Comments out the selected lines, or the cursor's line, with the
+    /// filetype's line comment (or block comment when it has none), or
+    /// uncomments them if they all are commented.
+    fn editor_toggle_comment(&mut self) {
+        let (start, end) = match self.editor_syntax.as_ref().and_then(comment_markers) {
+            Some(markers) => markers,
+            None => {
+                self.set_status_message(String::from("No comment syntax for this file"));
+                return;
+            }
+        };
+        if self.document.is_empty() {
+            return;
+        }
+        let (first, last) = self.selected_rows();
+        let lines = self.document.rows[first..=last]
+            .iter()
+            .map(|row| row.buf.clone())
+            .collect::<Vec<Vec<char>>>();
+        let toggled = toggle_comments(&lines, &start, &end);
+        for (i, (old, new)) in lines.iter().zip(toggled).enumerate() {
+            if first + i == self.position.y {
+                let change = new.len() as isize - old.len() as isize;
+                self.position.x =
+                    ((self.position.x as isize + change).max(0) as usize).min(new.len());
+            }
+            if *old != new {
+                let _ = self.document.replace_buf(first + i, new);
+                self.is_dirty = true;
+            }
+        }
+    }
+
+    fn editor_replace(&mut self, from: &str, to: &str) {
+        let mut count = 0;
+        for y in 0..self.document.len() {
+            let line = self.document.rows[y].buf.iter().collect::<String>();
+            let matches = line.matches(from).count();
+            if matches > 0 {
+                count += matches;
+                let _ = self
+                    .document
+                    .replace_buf(y, line.replace(from, to).chars().collect());
+            }
+        }
+        if count > 0 {
+            self.is_dirty = true;
+            self.position.x = self.position.x.min(self.get_current_row_buf_length());
         }
+        self.set_status_message(format!("Replaced {} occurrences", count));
     }
 
-    fn highlight_multi_comment(&self) -> bool {
-        match &self.editor_syntax {
-            Some(e_s) => return !e_s.multiline_comment_start.is_empty(),
-            None => return false,
+    /// Rewrites the selected lines, or every line, with `transform`, and
+    /// reports how many lines it left as `done` so many lines.
+    fn editor_transform_lines(
+        &mut self,
+        done: &str,
+        transform: impl FnOnce(Vec<Vec<char>>) -> Vec<Vec<char>>,
+    ) {
+        if self.document.is_empty() {
+            return;
+        }
+        let (first, last) = match self.selection() {
+            Some(_) => self.selected_rows(),
+            None => (0, self.document.len() - 1),
+        };
+        let lines = self.document.rows[first..=last]
+            .iter()
+            .map(|row| row.buf.clone())
+            .collect();
+        let lines = transform(lines);
+        let count = lines.len();
+        for (i, line) in lines.into_iter().enumerate() {
+            if self.document.rows[first + i].buf != line {
+                let _ = self.document.replace_buf(first + i, line);
+                self.is_dirty = true;
+            }
         }
+        if first + count <= last {
+            self.document.delete_lines(first + count, last);
+            self.is_dirty = true;
+        }
+        self.end_selection();
+        self.position.y = self.position.y.min(self.document.len() - 1);
+        self.position.x = self.position.x.min(self.get_current_row_buf_length());
+        self.set_status_message(format!("{} {} lines", done, count));
     }
 
-    fn new() -> Self {
-        let row_offset = 0;
-        let column_offset = 0;
-        let status_message = String::new();
-        let status_message_time = Utc::now();
-        let is_dirty = false;
-        let quit_times = QUIT_TIMES;
-        let should_quit = false;
-
-        Self {
-            position: Position::default(),
-            offset: Position::default(),
-            terminal: Terminal::default(),
-            document: Document::default(),
-            file_name: None,
-            editor_syntax: None,
-            status_message,
-            status_message_time,
-            is_dirty,
-            quit_times,
-            should_quit,
-            increment_find: IncrementFind::new(),
+    /// Pipes the selection, or else the whole document, through the shell
+    /// command `command` and puts what it prints in its place.
+    fn editor_filter(&mut self, command: &str) {
+        let (start, end) = match self.selection() {
+            Some(selection) => selection,
+            None => {
+                let last = self.document.len().saturating_sub(1);
+                let len = self.document.row(last).map_or(0, |row| row.buf.len());
+                ((0, 0), (len, last))
+            }
+        };
+        let text = self.document.text_range(start, end);
+        // most commands expect the last line to end in a newline.
+        let newline = !text.ends_with('\n');
+        let input = if newline {
+            format!("{}\n", text)
+        } else {
+            text.clone()
+        };
+        let output = match filter::run(command, &input, filter::TIMEOUT) {
+            Ok(output) => output,
+            Err(e) => return self.set_status_message(e),
+        };
+        let mut replacement = output.stdout;
+        if newline && replacement.ends_with('\n') {
+            replacement.pop();
+        }
+        if replacement != text {
+            self.document.delete_range(start, end);
+            self.document.insert_text(start, &replacement);
+            self.is_dirty = true;
+        }
+        if self.selection().is_some() {
+            self.end_selection();
+            self.position.x = start.0;
+            self.position.y = start.1;
+        }
+        self.position.y = self.position.y.min(self.document.len().saturating_sub(1));
+        self.position.x = self.position.x.min(self.get_current_row_buf_length());
+        match filter::first_line(&output.stderr) {
+            "" => self.set_status_message(format!("Filtered through `{}`", command)),
+            stderr => self.set_status_message(String::from(stderr)),
         }
     }
 
-    fn editor_select_syntax_hilight(&mut self) {
-        match &self.file_name {
-            None => return,
-            Some(name) => match Path::new(name).extension() {
-                Some(s) => {
-                    let extention = OsStr::to_str(s).unwrap_or("undefined");
-                    if ["c", "h", "cpp"].contains(&extention) {
-                        self.editor_syntax = Some(EditorSyntax {
-                            file_type: FileType::C,
-                            singleline_comment_start: String::from("//"),
-                            multiline_comment_start: String::from("/*"),
-                            multiline_comment_end: String::from("*/"),
-                            highlight_number: true,
-                            highlight_strings: true,
+    /// Changes the case of the selection, or of the whole document.
+    fn editor_change_case(&mut self, case: Case) {
+        let (start, end) = match self.selection() {
+            Some(selection) => selection,
+            None => {
+                return self.editor_transform_lines("Changed", |lines| {
+                    lines
+                        .iter()
+                        .map(|line| {
+                            let line = line.iter().collect::<String>();
+                            change_case(&line, case).chars().collect()
                         })
-                    }
-                }
-                None => return,
-            },
+                        .collect()
+                })
+            }
+        };
+        let text = self.document.text_range(start, end);
+        let changed = change_case(&text, case);
+        if changed != text {
+            self.document.delete_range(start, end);
+            self.document.insert_text(start, &changed);
+            self.is_dirty = true;
         }
+        self.end_selection();
+        self.position.x = start.0;
+        self.position.y = start.1;
     }
 
     fn saturated_add_x(&mut self) {
@@ -236,8 +1412,79 @@ impl Editor {
         self.is_dirty = true;
     }
 
+    /// The pairs typing closes automatically, if that is on.
+    fn auto_pairs(&self) -> &'static [(char, char)] {
+        match &self.editor_syntax {
+            Some(syntax) if self.auto_pairs => syntax.auto_pairs,
+            _ => &[],
+        }
+    }
+
+    /// The chars just before and at the cursor.
+    fn chars_around_cursor(&self) -> (Option<char>, Option<char>) {
+        let (x, y) = (self.position.x, self.position.y);
+        match self.document.row(y) {
+            Some(row) if x > 0 => (row.buf.get(x - 1).copied(), row.buf.get(x).copied()),
+            Some(row) => (None, row.buf.get(x).copied()),
+            None => (None, None),
+        }
+    }
+
     fn editor_insert_char(&mut self, c: char) {
-        self.editor_row_insert_char(c)
+        let pairs = self.auto_pairs();
+        let (before, after) = self.chars_around_cursor();
+        if let Some(close) = closer(pairs, c) {
+            if let Some((start, end)) = self.selection() {
+                self.editor_wrap_selection(start, end, c, close);
+                return;
+            }
+        }
+        // typing ends the region, so an opener typed later isn't wrapped
+        // around a mark set long before.
+        self.mark = None;
+        if types_over(pairs, c, after) {
+            self.position.x += 1;
+            return;
+        }
+        let closes = self
+            .editor_syntax
+            .as_ref()
+            .is_some_and(|syntax| syntax.indent_closers.contains(&c));
+        let blank = match self.document.row(self.position.y) {
+            Some(row) if row.buf.iter().all(|c| c.is_whitespace()) => Some(&row.buf),
+            _ => None,
+        };
+        if let Some(buf) = blank.filter(|_| closes) {
+            let indent = dedent(buf, self.indent_style);
+            self.position.x = indent.len();
+            let _ = self.document.replace_buf(self.position.y, indent);
+        }
+        self.editor_row_insert_char(c);
+        if let Some(close) = pair_for(pairs, c, before, after) {
+            self.editor_row_insert_char(close);
+            self.position.x -= 1;
+        }
+    }
+
+    /// Puts `open` and `close` around the selection, leaving the cursor
+    /// after `close`.
+    fn editor_wrap_selection(
+        &mut self,
+        start: (usize, usize),
+        end: (usize, usize),
+        open: char,
+        close: char,
+    ) {
+        self.document.insert_text(end, &close.to_string());
+        self.document.insert_text(start, &open.to_string());
+        self.position.x = if start.1 == end.1 {
+            end.0 + 2
+        } else {
+            end.0 + 1
+        };
+        self.position.y = end.1;
+        self.end_selection();
+        self.is_dirty = true;
     }
 
     fn editor_row_delete_character(&mut self) {
@@ -262,7 +1509,7 @@ impl Editor {
     }
 
     fn editor_delete_row(&mut self) {
-        self.document.rows.remove(self.position.y as usize);
+        self.document.remove_row(self.position.y as usize);
     }
 
     fn editor_row_append_string(&mut self, append_from_row_index: usize) {
@@ -285,6 +1532,24 @@ impl Editor {
         if self.position.x == 0 && self.position.y == 0 {
             return;
         }
+        let (x, y) = (self.position.x, self.position.y);
+        let (before, after) = self.chars_around_cursor();
+        if in_empty_pair(self.auto_pairs(), before, after) {
+            self.document.delete_range((x - 1, y), (x + 1, y));
+            self.position.x = x - 1;
+            self.is_dirty = true;
+            return;
+        }
+        let width = match self.document.row(y) {
+            Some(row) => backspace_width(&row.buf[..x], self.indent_style),
+            None => 1,
+        };
+        if width > 1 {
+            self.document.delete_range((x - width, y), (x, y));
+            self.position.x = x - width;
+            self.is_dirty = true;
+            return;
+        }
 
         if self.position.x > 0 {
             self.editor_row_delete_character();
@@ -319,251 +1584,803 @@ impl Editor {
     }
 
     fn editor_insert_new_line(&mut self) {
-        let mut new_el_vec = vec![];
         let (left_buf, right_buf) = self.split_line_resulted_from_enter_pressed();
-        if self.position.y == self.document.len() - 1 {
-            for i in 0..self.document.len() - 1 {
-                let el = &self.document.row(i as usize).unwrap();
-                new_el_vec.push(Row {
-                    buf: el.buf.clone(),
-                    render: el.render.clone(),
-                    highlight: vec![],
-                })
-            }
-            new_el_vec.push(Row {
-                buf: left_buf.clone(),
-                render: vec![],
-                highlight: vec![],
-            });
-            new_el_vec.push(Row {
-                buf: right_buf.clone(),
-                render: vec![],
-                highlight: vec![],
-            });
-        } else {
-            for (i, el) in self.document.rows.iter().enumerate() {
-                // new line
-                if i == self.position.y as usize + 1 {
-                    new_el_vec.push(Row {
-                        buf: right_buf.clone(),
+        let (openers, closers) = match &self.editor_syntax {
+            Some(syntax) => (syntax.indent_openers, syntax.indent_closers),
+            None => (&[][..], &[][..]),
+        };
+        let (lines, x) = break_line(&left_buf, &right_buf, openers, closers, self.indent_style);
+        let y = self.position.y;
+        for (i, buf) in lines.into_iter().enumerate() {
+            if i == 0 {
+                let _ = self.document.replace_buf(y, buf);
+            } else {
+                self.document.insert_row(
+                    y + i,
+                    Row {
+                        buf,
                         render: vec![],
                         highlight: vec![],
-                    })
+                    },
+                );
+            }
+        }
+        self.saturated_add_y();
+        self.position.x = x;
+        self.is_dirty = true;
+    }
+
+    fn editor_prompt<F>(
+        &mut self,
+        prompt: String,
+        mut history: Option<&mut History>,
+        completer: Option<Completer>,
+        mut incremental_callback: F,
+    ) -> String
+    where
+        F: FnMut(&mut Self, &str, &Key, bool),
+    {
+        let mut input = LineInput::new(completer);
+
+        loop {
+            self.set_status_message(format!("{}{}", prompt, input.text()));
+            self.prompt_cursor = Some(prompt.chars().count() + input.cursor());
+            self.editor_refresh_screen();
+
+            let key = match self.read_key() {
+                Ok(key) => key,
+                Err(_) => continue,
+            };
+            match key {
+                Key::Esc => {
+                    self.prompt_cursor = None;
+                    self.set_status_message(String::new());
+                    // keep what was typed so it can be searched for again.
+                    self.push_history(&mut history, &input.text());
+                    incremental_callback(self, &input.text(), &key, true);
+                    return String::new();
+                }
+                Key::Char('\n') if !input.is_empty() => {
+                    self.prompt_cursor = None;
+                    self.set_status_message(String::new());
+                    self.push_history(&mut history, &input.text());
+                    incremental_callback(self, &input.text(), &key, true);
+                    return input.text();
+                }
+                Key::Char('\n') => {}
+                Key::Up | Key::Down if history.is_some() => {
+                    let entry = match history.as_mut() {
+                        Some(history) if key == Key::Up => history.previous(&input.text()),
+                        Some(history) => history.next(),
+                        None => None,
+                    };
+                    if let Some(entry) = entry {
+                        input.set_text(&entry);
+                        incremental_callback(self, &input.text(), &key, false);
+                    }
                 }
+                _ => {
+                    input.handle_key(&key);
+                    incremental_callback(self, &input.text(), &key, false);
+                }
+            }
+        }
+    }
 
-                // splited line
-                if i == self.position.y as usize {
-                    new_el_vec.push(Row {
-                        buf: left_buf.clone(),
-                        render: vec![],
-                        highlight: vec![],
-                    })
-                // just  line
-                } else {
-                    new_el_vec.push(Row {
-                        buf: el.buf.clone(),
-                        render: vec![],
-                        highlight: vec![],
-                    })
+    fn push_history(&mut self, history: &mut Option<&mut History>, input: &str) {
+        if let Some(history) = history {
+            if let Err(e) = history.push(input) {
+                self.set_status_message(e);
+            }
+        }
+    }
+
+    fn editor_save(&mut self) {
+        if self.file_name.is_none() {
+            let file_name = self.editor_prompt(
+                String::from("Save as: "),
+                None,
+                Some(complete_path),
+                |_, _, _, _| {},
+            );
+            if file_name.is_empty() {
+                self.set_status_message(String::from("Save aborted"));
+                return;
+            }
+            self.file_name = Some(file_name);
+            self.editor_select_syntax_hilight();
+        }
+        let formatted = self.editor_format();
+        match &self.file_name {
+            None => return,
+            Some(s) => match File::create(s) {
+                Ok(mut f) => {
+                    for i in 0..self.document.len() {
+                        let buf = &self
+                            .document
+                            .row(i as usize)
+                            .unwrap()
+                            .buf
+                            .iter()
+                            .cloned()
+                            .collect::<String>();
+                        f.write_all(buf.as_bytes()).unwrap();
+                        f.write_all(b"\r\n").unwrap();
+                    }
+                    self.is_dirty = false;
+                    self.document.mark_saved((self.position.x, self.position.y));
+                    let written = self.get_editor_buffer_length();
+                    match formatted {
+                        Ok(()) => {
+                            self.set_status_message(format!("{} bytes written to disk", written))
+                        }
+                        Err(e) => self.set_status_message(format!(
+                            "{} bytes written to disk unformatted: {}",
+                            written, e
+                        )),
+                    }
+                }
+
+                Err(e) => self.set_status_message(format!("Can't save! I/O error: {}", e)),
+            },
+        }
+    }
+
+    /// Runs the document through the formatter configured for its
+    /// filetype, keeping the cursor on the same text.
+    fn editor_format(&mut self) -> Result<(), String> {
+        let file_type = match &self.editor_syntax {
+            Some(syntax) => syntax.file_type,
+            None => return Ok(()),
+        };
+        let command = match self.formatters.iter().find(|(t, _)| *t == file_type) {
+            Some((_, command)) => command.clone(),
+            None => return Ok(()),
+        };
+        let mut text = String::new();
+        for row in &self.document.rows {
+            text.extend(row.buf.iter());
+            text.push('\n');
+        }
+        let formatted = filter::run(&command, &text, filter::TIMEOUT)?.stdout;
+        if formatted == text {
+            return Ok(());
+        }
+        // rather than wipe the file out.
+        if formatted.trim().is_empty() && !text.trim().is_empty() {
+            return Err(format!("`{}` printed nothing", command));
+        }
+        let cursor = moved_cursor(&text, &formatted, (self.position.x, self.position.y));
+        let last = self.document.len().saturating_sub(1);
+        let len = self.document.row(last).map_or(0, |row| row.buf.len());
+        self.document.delete_range((0, 0), (len, last));
+        let formatted = formatted.strip_suffix('\n').unwrap_or(&formatted);
+        self.document.insert_text((0, 0), formatted);
+        self.end_selection();
+        self.position.y = cursor.1.min(self.document.len().saturating_sub(1));
+        self.position.x = cursor.0.min(self.get_current_row_buf_length());
+        Ok(())
+    }
+
+    fn editor_go_to_line(&mut self) {
+        let input = self.editor_prompt(String::from("Go to line: "), None, None, |_, _, _, _| {});
+        if !input.is_empty() {
+            self.editor_goto(&input);
+        }
+    }
+
+    fn editor_goto(&mut self, input: &str) {
+        match input.trim().parse::<usize>() {
+            Ok(line) if line > 0 && !self.document.is_empty() => {
+                self.position.y = line.min(self.document.len()) - 1;
+                self.position.x = 0;
+            }
+            _ => self.set_status_message(format!("Not a line number: {}", input)),
+        }
+    }
+
+    fn on_incremental_find(&mut self, query: &str, key: &Key, end: bool) {
+        if end {
+            self.increment_find = IncrementFind::new();
+            return;
+        }
+        if query != self.increment_find.query {
+            self.increment_find.search(query, &self.document.rows);
+        } else {
+            match key {
+                Key::Ctrl('n') => self.increment_find.step(IncrementFindDirection::Forward),
+                Key::Ctrl('p') => self.increment_find.step(IncrementFindDirection::Backward),
+                _ => {}
+            }
+        }
+
+        if let Some((y, column, _)) = self.increment_find.current_match() {
+            let (x, y) = buffer_position(&self.document.rows, (y, column), self.tab_stop);
+            self.position.x = x;
+            self.position.y = y;
+        }
+    }
+
+    fn editor_find(&mut self) {
+        let saved_cursor_x = self.position.x;
+        let saved_cursor_y = self.position.y;
+        let saved_column_offset = self.offset.x;
+        let saved_row_offset = self.offset.y;
+
+        self.increment_find.origin = render_position(
+            &self.document.rows,
+            (self.position.x, self.position.y),
+            self.tab_stop,
+        );
+        let mut history = std::mem::take(&mut self.search_history);
+        let query = self.editor_prompt(
+            String::from("Search:"),
+            Some(&mut history),
+            None,
+            Self::on_incremental_find,
+        );
+        self.search_history = history;
+        if query.is_empty() {
+            self.position.x = saved_cursor_x;
+            self.position.y = saved_cursor_y;
+            self.offset.x = saved_column_offset;
+            self.offset.y = saved_row_offset;
+        }
+    }
+
+    /// Jumps to the next or previous match of the last search without
+    /// opening the prompt.
+    fn editor_find_again(&mut self, direction: IncrementFindDirection) {
+        let query = match self.search_history.last() {
+            Some(query) => String::from(query),
+            None => {
+                self.set_status_message(String::from("No previous search"));
+                return;
+            }
+        };
+        let mut find = IncrementFind::new();
+        // forwards, the match the cursor is on doesn't count.
+        let x = match direction {
+            IncrementFindDirection::Forward => self.position.x + 1,
+            IncrementFindDirection::Backward => self.position.x,
+        };
+        find.origin = render_position(&self.document.rows, (x, self.position.y), self.tab_stop);
+        find.search(&query, &self.document.rows);
+        if let IncrementFindDirection::Backward = direction {
+            find.step(direction);
+        }
+        if let Some((y, column, _)) = find.current_match() {
+            let (x, y) = buffer_position(&self.document.rows, (y, column), self.tab_stop);
+            self.position.x = x;
+            self.position.y = y;
+        }
+        self.set_status_message(format!(
+            "Search: {} ({})",
+            query,
+            find.summary().unwrap_or_default()
+        ));
+    }
+
+    /// The next key of the macro being played, or else from the terminal.
+    /// Keys from the terminal go into the macro being recorded.
+    fn read_key(&mut self) -> Result<Key, std::io::Error> {
+        if let Some(key) = self.replay.pop_front() {
+            return Ok(key);
+        }
+        let key = Terminal::read_key()?;
+        self.macros.record(key);
+        Ok(key)
+    }
+
+    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
+        let pending = self.vim.as_ref().is_some_and(|vim| vim.parser.is_pending());
+        if self.pending_keys.is_empty() && !pending {
+            self.macros.begin_command();
+        }
+        let pressed_key = self.read_key()?;
+        dbg!(&pressed_key);
+        self.editor_handle_key(pressed_key);
+        Ok(())
+    }
+
+    fn editor_handle_key(&mut self, key: Key) {
+        if self.vim.is_some() {
+            self.vim_key(key);
+        } else {
+            self.editor_key(key);
+        }
+    }
+
+    /// Starts recording keys into `register`, or stops the recording.
+    fn editor_record_macro(&mut self, register: char) {
+        if let Some((register, len)) = self.macros.stop() {
+            self.set_status_message(format!("Recorded macro {} ({} keys)", register, len));
+        } else if !self.playing.is_empty() {
+            self.editor_abort_macro(String::from("Can't record a macro while playing one"));
+        } else {
+            self.macros.start(register);
+            self.set_status_message(format!("Recording macro {}", register));
+        }
+    }
+
+    /// Plays the macro in `register` `count` times as one undo step.
+    fn editor_play_macro(&mut self, register: char, count: usize) {
+        let cursor = (self.position.x, self.position.y);
+        self.document.begin_undo_group(cursor);
+        self.editor_replay(register, count);
+        self.document.end_undo_group();
+        self.editor_end_macros();
+    }
+
+    /// Plays the macro in `register` at the start of each selected line, or
+    /// the cursor's line, working up from the bottom so lines it adds or
+    /// removes don't throw off the rest.
+    fn editor_play_macro_lines(&mut self, register: char) {
+        if self.document.is_empty() {
+            return;
+        }
+        let (first, last) = self.selected_rows();
+        let cursor = (self.position.x, self.position.y);
+        self.end_selection();
+        self.document.begin_undo_group(cursor);
+        for y in (first..=last).rev() {
+            if y >= self.document.len() || self.macro_aborted || self.should_quit {
+                break;
+            }
+            self.position.x = 0;
+            self.position.y = y;
+            self.editor_replay(register, 1);
+        }
+        self.document.end_undo_group();
+        self.editor_end_macros();
+    }
+
+    fn editor_replay(&mut self, register: char, count: usize) {
+        let keys = match self.macros.get(register) {
+            Some(keys) => keys.to_vec(),
+            None => return self.editor_abort_macro(format!("No macro in register {}", register)),
+        };
+        if self.playing.contains(&register) {
+            return self.editor_abort_macro(format!("Macro {} plays itself", register));
+        }
+        self.macros.last = Some(register);
+        // a macro played by another one takes over the keys until it ends.
+        let outer = std::mem::take(&mut self.replay);
+        self.playing.push(register);
+        for _ in 0..count {
+            self.replay = keys.iter().copied().collect();
+            while let Some(key) = self.replay.pop_front() {
+                self.editor_handle_key(key);
+                if self.macro_aborted || self.should_quit {
+                    break;
+                }
+            }
+            if self.macro_aborted || self.should_quit {
+                break;
+            }
+        }
+        self.playing.pop();
+        self.replay = outer;
+    }
+
+    fn editor_abort_macro(&mut self, message: String) {
+        self.replay.clear();
+        self.macro_aborted = !self.playing.is_empty();
+        self.set_status_message(message);
+    }
+
+    // once the outermost macro is done.
+    fn editor_end_macros(&mut self) {
+        if self.playing.is_empty() {
+            self.macro_aborted = false;
+            self.pending_keys.clear();
+        }
+    }
+
+    fn editor_key(&mut self, pressed_key: Key) {
+        self.pending_keys.push(pressed_key);
+        match self.keymap.lookup(&self.pending_keys) {
+            Lookup::Command(command_line) => {
+                self.pending_keys.clear();
+                self.editor_run_command(&command_line);
+            }
+            Lookup::Prefix => {
+                self.set_status_message(format!("{} -", keys_to_string(&self.pending_keys)))
+            }
+            Lookup::None => {
+                let keys = std::mem::take(&mut self.pending_keys);
+                let previous = self.last_command.take();
+                let cursor = (self.position.x, self.position.y);
+                match pressed_key {
+                    event::Key::Char('\n') if keys.len() == 1 => {
+                        self.document.begin_undo_step(cursor);
+                        self.editor_each_cursor(Self::editor_insert_new_line);
+                    }
+                    event::Key::Char(c) if keys.len() == 1 => {
+                        // a run of typed chars is undone in one go.
+                        if previous != Some("self-insert") || self.rectangle_bounds().is_some() {
+                            self.document.begin_undo_step(cursor);
+                        }
+                        // typing over a rectangle types on each of its lines.
+                        if self.rectangle_bounds().is_some() {
+                            self.editor_rectangle_to_cursors(true);
+                        }
+                        self.editor_each_cursor(|editor| editor.editor_insert_char(c));
+                        self.last_command = Some("self-insert");
+                    }
+                    _ if keys.len() > 1 => {
+                        self.set_status_message(format!("{} is not bound", keys_to_string(&keys)))
+                    }
+                    _ => {}
                 }
             }
         }
-        self.document.rows = new_el_vec;
-        self.saturated_add_y();
-        self.position.x = 0
     }
 
-    fn editor_prompt<F>(&mut self, prompt: String, mut incremental_callback: F) -> String
-    where
-        F: FnMut(&mut Self, &str, &Key, bool),
-    {
-        let mut input = String::new();
-        self.set_status_message(format!("{}{}", prompt, input));
-        self.editor_refresh_screen();
-
-        loop {
-            let r = Terminal::read_key();
-            match &r {
-                Ok(Key::Esc) => {
-                    self.set_status_message(String::new());
-                    incremental_callback(self, &input, &r.unwrap(), true);
-                    return String::new();
+    fn vim_key(&mut self, key: Key) {
+        let normal = self
+            .vim
+            .as_ref()
+            .is_some_and(|vim| vim.mode == Mode::Normal);
+        if normal && self.rectangle_bounds().is_some() && self.vim_rectangle_key(key) {
+            return;
+        }
+        let vim = match &mut self.vim {
+            Some(vim) => vim,
+            None => return,
+        };
+        let mode = vim.mode;
+        if mode == Mode::Insert {
+            vim.recording.push(key);
+            if key == Key::Esc {
+                vim.last_change = std::mem::take(&mut vim.recording);
+                vim.mode = Mode::Normal;
+                self.position.x = self.position.x.saturating_sub(1);
+                self.vim_clamp_cursor();
+            } else {
+                self.editor_key(key);
+            }
+            return;
+        }
+
+        let c = match key {
+            Key::Char(c) => c,
+            // as in Vim, rather than editing through the modeless bindings.
+            Key::Backspace => 'h',
+            Key::Delete => 'x',
+            Key::Esc => {
+                vim.parser = Parser::default();
+                vim.mode = Mode::Normal;
+                return;
+            }
+            Key::Ctrl('r') => {
+                vim.parser = Parser::default();
+                self.editor_undo(true);
+                return;
+            }
+            Key::Ctrl('v') => {
+                vim.parser = Parser::default();
+                vim.mode = Mode::Normal;
+                self.mark = Some((self.position.x, self.position.y));
+                self.block = true;
+                return;
+            }
+            // everything else keeps its usual binding.
+            _ => {
+                self.editor_key(key);
+                self.vim_clamp_cursor();
+                return;
+            }
+        };
+        if c == 'q' && !vim.parser.is_pending() && self.macros.recording().is_some() {
+            self.editor_record_macro(DEFAULT_REGISTER);
+            return;
+        }
+        if !vim.parser.is_pending() {
+            vim.recording.clear();
+        }
+        vim.recording.push(key);
+        let register = vim.parser.register();
+        if let Parsed::Done { count, action } = vim.parser.feed(c, mode == Mode::Visual) {
+            if mode == Mode::Normal && action.is_change() {
+                vim.last_change = vim.recording.clone();
+            }
+            vim.register = register;
+            self.vim_run(count, action);
+        }
+    }
+
+    fn vim_run(&mut self, count: usize, action: Action) {
+        let cursor = (self.position.x, self.position.y);
+        self.document.begin_undo_step(cursor);
+        if !matches!(action, Action::Insert(_)) {
+            self.cursors.clear();
+        }
+        match action {
+            Action::Move(motion) => {
+                let (x, y) = apply_motion(&self.document.rows, cursor, motion, count);
+                self.position.x = x;
+                self.position.y = y;
+            }
+            Action::Operate(operator, motion) => {
+                let on_word = self
+                    .document
+                    .row(cursor.1)
+                    .and_then(|row| row.buf.get(cursor.0))
+                    .is_some_and(|c| !c.is_whitespace());
+                // `cw` changes to the end of the word, like `ce`.
+                let motion = match motion {
+                    Motion::WordForward if operator == Operator::Change && on_word => {
+                        Motion::WordEnd
+                    }
+                    _ => motion,
+                };
+                let mut to = apply_motion(&self.document.rows, cursor, motion, count);
+                // `dw` on the last word of a line stops at the line break.
+                if motion == Motion::WordForward && to.1 > cursor.1 {
+                    let y = to.1 - 1;
+                    to = (self.document.rows[y].buf.len(), y);
+                }
+                self.vim_operate(
+                    operator,
+                    cursor,
+                    to,
+                    motion.is_linewise(),
+                    motion.is_inclusive(),
+                );
+            }
+            Action::OperateLines(operator) => {
+                let last = (cursor.1 + count - 1).min(self.document.len().saturating_sub(1));
+                self.vim_operate(operator, (0, cursor.1), (0, last), true, false);
+            }
+            Action::OperateSelection(operator) => {
+                let anchor = self.vim.as_ref().map_or(cursor, |vim| vim.anchor);
+                self.vim_set_mode(Mode::Normal);
+                self.vim_operate(operator, anchor, cursor, false, true);
+            }
+            Action::DeleteChar => {
+                let len = self.get_current_row_buf_length();
+                if len > 0 {
+                    let to = ((cursor.0 + count).min(len), cursor.1);
+                    self.vim_operate(Operator::Delete, cursor, to, false, false);
                 }
-                Ok(Key::Backspace) | Ok(Key::Delete) => {
-                    input.pop();
+            }
+            Action::Insert(at) => {
+                if self.document.is_empty() {
+                    self.document.insert_text((0, 0), "");
                 }
-                Ok(Key::Right) | Ok(Key::Left) | Ok(Key::Down) | Ok(Key::Up)
-                | Ok(Key::Char('\n')) => match &r {
-                    Ok(key) => incremental_callback(self, &input, &key, false),
-                    Err(_) => {}
-                },
-                Ok(event::Key::Char(c)) => {
-                    eprintln!("char!!!{}", &c);
-                    input.push(c.clone());
-                    incremental_callback(self, &input, &r.unwrap(), false);
+                let len = self.get_current_row_buf_length();
+                match at {
+                    InsertAt::Cursor => {}
+                    InsertAt::After => self.position.x = (cursor.0 + 1).min(len),
+                    InsertAt::LineStart => self.position.x = 0,
+                    InsertAt::LineEnd => self.position.x = len,
+                    InsertAt::LineBelow => {
+                        self.document.insert_text((len, cursor.1), "\n");
+                        self.position.x = 0;
+                        self.position.y = cursor.1 + 1;
+                        self.is_dirty = true;
+                    }
+                    InsertAt::LineAbove => {
+                        self.document.insert_text((0, cursor.1), "\n");
+                        self.position.x = 0;
+                        self.is_dirty = true;
+                    }
                 }
-                _ => {}
+                self.vim_set_mode(Mode::Insert);
+                // what is typed next belongs to the same undo step.
+                self.last_command = Some("self-insert");
             }
-            self.set_status_message(format!("{}{}", prompt, input));
-            self.editor_refresh_screen();
-        }
-
-        prompt
-    }
-
-    fn editor_save(&mut self) {
-        // TODO: Save as ...
-        // TODO: editor_select_syntax_hilight after Save as ...
-        match &self.file_name {
-            None => return,
-            Some(s) => match File::create(s) {
-                Ok(mut f) => {
-                    for i in 0..self.document.len() {
-                        let buf = &self
-                            .document
-                            .row(i as usize)
-                            .unwrap()
-                            .buf
-                            .iter()
-                            .cloned()
-                            .collect::<String>();
-                        f.write_all(buf.as_bytes()).unwrap();
-                        f.write_all(b"\r\n").unwrap();
+            Action::Paste { after } => self.vim_paste(after, count),
+            Action::ToggleVisual => {
+                let mode = match self.vim.as_ref().map(|vim| vim.mode) {
+                    Some(Mode::Visual) => Mode::Normal,
+                    _ => Mode::Visual,
+                };
+                if let Some(vim) = &mut self.vim {
+                    vim.anchor = cursor;
+                }
+                self.vim_set_mode(mode);
+            }
+            Action::Repeat => {
+                let keys = self
+                    .vim
+                    .as_ref()
+                    .map(|vim| vim.last_change.clone())
+                    .unwrap_or_default();
+                for _ in 0..count {
+                    for key in keys.iter() {
+                        self.vim_key(*key);
                     }
-                    self.is_dirty = false;
-                    self.set_status_message(format!(
-                        "{} bytes written to disk",
-                        self.get_editor_buffer_length()
-                    ));
                 }
-
-                Err(e) => self.set_status_message(format!("Can't save! I/O error: {}", e)),
-            },
+            }
+            Action::RecordMacro(register) => self.editor_record_macro(register),
+            Action::PlayMacro(register) => {
+                let register = match register {
+                    '@' => self.macros.last.unwrap_or(DEFAULT_REGISTER),
+                    _ => register,
+                };
+                self.editor_play_macro(register, count);
+            }
+            Action::Undo => {
+                for _ in 0..count {
+                    self.editor_undo(false);
+                }
+            }
+            Action::CommandLine => self.editor_command_line(),
+        }
+        if self.vim.as_ref().map(|vim| vim.mode) != Some(Mode::Insert) {
+            self.vim_clamp_cursor();
         }
     }
 
-    fn editor_row_rx2cx(&mut self, render_x: usize) -> usize {
-        let mut current_render_x = 0;
-        let mut target_cursor_x = 0;
-
-        let current_buf_row = &self.document.row(self.position.y as usize).unwrap().buf;
-        for (_, c) in current_buf_row.iter().enumerate() {
-            if *c == '\t' {
-                current_render_x = current_render_x + (KILL_TAB_STOP as usize - 1)
-                    - (current_render_x % KILL_TAB_STOP as usize)
-            };
-            current_render_x = current_render_x + 1;
-            target_cursor_x = target_cursor_x + 1;
+    /// Deletes, changes or yanks the text between `from` and `to`.
+    fn vim_operate(
+        &mut self,
+        operator: Operator,
+        from: (usize, usize),
+        to: (usize, usize),
+        linewise: bool,
+        inclusive: bool,
+    ) {
+        if self.document.is_empty() {
+            return;
+        }
+        let ((start_x, start_y), (end_x, end_y)) = ordered(from, to);
+        let row_len = |document: &Document, y: usize| document.rows[y].buf.len();
+        let (start, end) = if linewise {
+            ((0, start_y), (row_len(&self.document, end_y), end_y))
+        } else if inclusive {
+            let end_x = (end_x + 1).min(row_len(&self.document, end_y));
+            ((start_x, start_y), (end_x, end_y))
+        } else {
+            ((start_x, start_y), (end_x, end_y))
+        };
+        let register = Register {
+            text: self.document.text_range(start, end),
+            linewise,
+        };
+        let name = self.vim.as_ref().and_then(|vim| vim.register);
+        if let Err(e) = self.registers.store(name, register) {
+            self.set_status_message(e);
+        }
 
-            if current_render_x >= render_x {
-                return target_cursor_x;
+        match operator {
+            Operator::Yank => {}
+            Operator::Delete if linewise => {
+                self.document.delete_lines(start_y, end_y);
+                self.is_dirty = true;
             }
+            Operator::Delete | Operator::Change => {
+                self.document.delete_range(start, end);
+                self.is_dirty = true;
+            }
+        }
+        self.position.x = start.0;
+        self.position.y = start.1.min(self.document.len().saturating_sub(1));
+        if operator == Operator::Change {
+            self.vim_set_mode(Mode::Insert);
         }
-        return target_cursor_x;
     }
 
-    fn on_incremental_find(&mut self, query: &str, key: &Key, end: bool) {
-        if end {
-            self.increment_find = IncrementFind::new()
+    fn vim_paste(&mut self, after: bool, count: usize) {
+        let name = self.vim.as_ref().and_then(|vim| vim.register);
+        let register = match self.registers.get(name.unwrap_or('"')) {
+            Some(register) => register.clone(),
+            None => return,
+        };
+        let (x, y) = (self.position.x, self.position.y);
+        if register.linewise {
+            let lines = vec![register.text.as_str(); count].join("\n");
+            if self.document.is_empty() || !after {
+                self.document.insert_text((0, y), &format!("{}\n", lines));
+                self.position.y = y;
+            } else {
+                let len = self.get_current_row_buf_length();
+                self.document.insert_text((len, y), &format!("\n{}", lines));
+                self.position.y = y + 1;
+            }
+            self.position.x = 0;
+        } else {
+            let len = self.get_current_row_buf_length();
+            let at = if after { (x + 1).min(len) } else { x };
+            let (end_x, end_y) = self
+                .document
+                .insert_text((at, y), &register.text.repeat(count));
+            self.position.x = end_x.saturating_sub(1);
+            self.position.y = end_y;
         }
+        self.is_dirty = true;
+    }
+
+    /// The keys that act on a block selection in normal mode; the rest
+    /// move the cursor as usual.
+    fn vim_rectangle_key(&mut self, key: Key) -> bool {
+        let cursor = (self.position.x, self.position.y);
         match key {
-            Key::Right | Key::Down | Key::Char('\n') => {
-                self.increment_find.direction = IncrementFindDirection::Forward
+            Key::Char('y') => self.editor_kill_rectangle(false),
+            Key::Char('d') | Key::Char('x') => {
+                self.document.begin_undo_step(cursor);
+                self.editor_kill_rectangle(true);
             }
-            Key::Left | Key::Up => self.increment_find.direction = IncrementFindDirection::Backward,
-            _ => {
-                self.increment_find = IncrementFind::new();
+            Key::Char('c') | Key::Char('I') => {
+                self.document.begin_undo_step(cursor);
+                self.editor_rectangle_to_cursors(key == Key::Char('c'));
+                self.vim_set_mode(Mode::Insert);
+                self.last_command = Some("self-insert");
             }
-        };
+            Key::Esc => self.mark = None,
+            _ => return false,
+        }
+        true
+    }
 
-        let mut current_row = -1;
-        if let Some(i) = self.increment_find.last_mached_row {
-            current_row = i
+    fn vim_set_mode(&mut self, mode: Mode) {
+        if let Some(vim) = &mut self.vim {
+            vim.mode = mode;
         }
+    }
 
-        for _ in 0..self.document.len() {
-            match self.increment_find.direction {
-                IncrementFindDirection::Forward => current_row = current_row + 1,
-                IncrementFindDirection::Backward => current_row = current_row - 1,
-            }
-            if current_row == -1 {
-                current_row = self.document.len() as i16 - 1
-            } else if current_row == self.document.len() as i16 {
-                current_row = 0
-            }
+    fn vim_clamp_cursor(&mut self) {
+        let (x, y) = clamp_to_char(&self.document.rows, (self.position.x, self.position.y));
+        self.position.x = x;
+        self.position.y = y;
+    }
 
-            let row = &self
-                .document
-                .row(current_row as usize)
-                .unwrap()
-                .render_string();
-
-            if let Some(x) = row.find(&query) {
-                self.increment_find.last_mached_row = Some(current_row);
-                self.position.y = current_row as usize;
-                self.position.x = self.editor_row_rx2cx(x);
-
-                for _ in 0..query.chars().count() {
-                    self.document.replace_char_highlight(
-                        current_row as usize,
-                        x + 1,
-                        Highlight::Match,
-                    );
-                }
-                break;
+    /// The visual selection or the marked region, from its start up to
+    /// (not including) its end.
+    fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let cursor = (self.position.x, self.position.y);
+        match &self.vim {
+            Some(vim) if vim.mode == Mode::Visual => {
+                let (start, end) = ordered(vim.anchor, cursor);
+                Some((start, (end.0 + 1, end.1)))
             }
+            Some(_) => None,
+            None if self.block => None,
+            None => Some(ordered(self.mark?, cursor)),
         }
     }
 
-    fn editor_find(&mut self) {
-        let saved_cursor_x = self.position.x;
-        let saved_cursor_y = self.position.y;
-        let saved_column_offset = self.offset.x;
-        let saved_row_offset = self.offset.y;
-
-        let query = self.editor_prompt(String::from("Search:"), Self::on_incremental_find);
-        if query.is_empty() {
-            self.position.x = saved_cursor_x;
-            self.position.y = saved_cursor_y;
-            self.offset.x = saved_column_offset;
-            self.offset.y = saved_row_offset;
-        }
+    /// The bracket under the cursor, or else just before it, and its
+    /// partner.
+    fn matching_bracket(&self) -> Option<((usize, usize), (usize, usize))> {
+        let (x, y) = (self.position.x, self.position.y);
+        let buf = &self.document.row(y)?.buf;
+        let x = if buf.get(x).is_some_and(|c| is_bracket(*c)) {
+            x
+        } else if x > 0 && is_bracket(buf[x - 1]) {
+            x - 1
+        } else {
+            return None;
+        };
+        let partner = find_match(&self.document.rows, (x, y), self.tab_stop)?;
+        Some(((x, y), partner))
     }
 
-    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
-        dbg!(&pressed_key);
-        match pressed_key {
-            event::Key::Ctrl('c') | event::Key::Ctrl('q') => {
-                //if self.is_dirty && self.quit_times > 0 {
-                //    // TODO 他の作業をしたらquit_timesが回復するように
-                //    self.set_status_message(format!(
-                //    "WARNING!!! File has unsaved changes. Press Ctr-Q|C {} more times to quit",
-                //    self.quit_times
-                //));
-                //    self.quit_times = self.quit_times - 1;
-                //} else {
-                self.should_quit = true;
-                //}
-            }
-            event::Key::Backspace | event::Key::Ctrl('h') | event::Key::Delete => {
-                self.editor_delete_char();
-            }
-            event::Key::Ctrl('s') => self.editor_save(),
-            event::Key::Ctrl('f') => self.editor_find(),
-            event::Key::Left | event::Key::Right | event::Key::Up | event::Key::Down => {
-                self.move_cursor(pressed_key)
-            }
-            event::Key::Char(c) => {
-                if c == '\n' {
-                    self.editor_insert_new_line()
-                } else {
-                    self.editor_insert_char(c)
-                }
+    /// The render columns of `file_row` inside the selection.
+    fn selection_range(&self, file_row: usize, row: &Row) -> Option<(usize, usize)> {
+        if let Some(((top, bottom), (left, right))) = self.rectangle_bounds() {
+            if file_row < top || file_row > bottom {
+                return None;
             }
-            _ => {}
+            // an empty rectangle shows as a column one char wide.
+            return Some((left, right.max(left + 1)));
         }
-        Ok(())
+        let (start, end) = self.selection()?;
+        if file_row < start.1 || file_row > end.1 {
+            return None;
+        }
+        let from = if file_row == start.1 { start.0 } else { 0 };
+        let to = if file_row == end.1 {
+            end.0
+        } else {
+            row.buf.len() + 1
+        };
+        Some((
+            row.render_index(from, self.tab_stop),
+            row.render_index(to, self.tab_stop),
+        ))
     }
 
     fn move_cursor(&mut self, key: Key) {
@@ -592,8 +2409,7 @@ impl Editor {
         let row = &self.document.row(self.position.y as usize).unwrap();
         for i in 0..self.position.x {
             if row.buf[i as usize] == '\t' {
-                render_x =
-                    render_x + (KILL_TAB_STOP as usize - 1) - (render_x % KILL_TAB_STOP as usize)
+                render_x = render_x + (self.tab_stop - 1) - (render_x % self.tab_stop)
             }
             render_x = render_x + 1;
         }
@@ -624,11 +2440,18 @@ impl Editor {
         eprintln!("{:?}", self.document.row(self.position.y).unwrap().buf);
         eprintln!("{:?}", self.document.row(self.position.y).unwrap().render);
 
-        Terminal::cursor_position(&Position {
-            x: self.position.x - self.offset.x,
-            y: self.position.y - self.offset.y,
-            render_x: 0,
-        });
+        match self.prompt_cursor {
+            Some(x) => Terminal::cursor_position(&Position {
+                x,
+                y: self.terminal.window_size_height as usize + 1,
+                render_x: 0,
+            }),
+            None => Terminal::cursor_position(&Position {
+                x: self.position.x - self.offset.x,
+                y: self.position.y - self.offset.y,
+                render_x: 0,
+            }),
+        }
 
         if self.should_quit {
             Terminal::clear_screen();
@@ -697,8 +2520,16 @@ impl Editor {
         if self.is_dirty {
             modified_status = "(modified)"
         }
+        let mut mode = match &self.vim {
+            Some(vim) => format!("-- {} -- ", vim.mode.name()),
+            None => String::new(),
+        };
+        if let Some(register) = self.macros.recording() {
+            mode.push_str(&format!("recording @{} ", register));
+        }
         let mut status = format!(
-            "{} - {} lines {}",
+            "{}{} - {} lines {}",
+            mode,
             display_file_name,
             self.document.len(),
             modified_status
@@ -726,13 +2557,11 @@ impl Editor {
         }
         let status_line = format!("{}{}", status, right_status);
 
-        print!(
-            "{}{}{}{}",
-            color::Bg(color::LightMagenta),
-            color::Fg(color::Black),
-            status_line,
-            style::Reset
+        Terminal::set_style(
+            &self.theme.ui_style(UiElement::StatusBar),
+            self.color_support,
         );
+        print!("{}{}", status_line, style::Reset);
         print!("\r\n");
     }
 
@@ -741,19 +2570,21 @@ impl Editor {
         if self.status_message_time + Duration::seconds(5) < Utc::now() {
             return;
         }
-        for (i, c) in self.status_message.chars().enumerate() {
+        let message = match self.increment_find.summary() {
+            Some(summary) => format!("{}  ({})", self.status_message, summary),
+            None => self.status_message.clone(),
+        };
+        for (i, c) in message.chars().enumerate() {
             if i < self.terminal.window_size_width as usize {
                 message_line.push(c)
             }
         }
 
-        print!(
-            "{}{}{}{}",
-            color::Bg(color::LightMagenta),
-            color::Fg(color::Black),
-            message_line,
-            style::Reset
-        )
+        Terminal::set_style(
+            &self.theme.ui_style(UiElement::MessageBar),
+            self.color_support,
+        );
+        print!("{}{}", message_line, style::Reset)
     }
 
     fn editor_update_row(&mut self) {
@@ -762,7 +2593,7 @@ impl Editor {
             let mut render = vec![]; // TODO: pushではなくて、メモリ確保して処理する
             for c in line.buf.iter() {
                 if *c == '\t' {
-                    for _ in 0..KILL_TAB_STOP {
+                    for _ in 0..self.tab_stop {
                         render.push(' ');
                     }
                 } else {
@@ -779,27 +2610,70 @@ impl Editor {
         self.editor_update_syntax()
     }
 
-    fn draw_row(&self, row: &Row) {
+    fn draw_row(&self, file_row: usize, row: &Row, brackets: &[(usize, usize)]) {
+        let mut current_style: Option<Style> = None;
+        let selection = self.selection_range(file_row, row);
+        let brackets = brackets
+            .iter()
+            .filter(|(_, y)| *y == file_row)
+            .map(|(x, _)| row.render_index(*x, self.tab_stop))
+            .collect::<Vec<usize>>();
+        let cursors = self
+            .cursors
+            .iter()
+            .filter(|(_, y)| *y == file_row)
+            .map(|(x, _)| row.render_index(*x, self.tab_stop))
+            .collect::<Vec<usize>>();
         for (j, c) in row.render.iter().enumerate() {
-            let mut current_color: Highlight = Highlight::Normal;
             if j >= self.offset.x as usize
                 && j < (self.terminal.window_size_width + self.offset.x as u16) as usize
             {
-                let peek_color = row.highlight[j];
-                if current_color != peek_color {
-                    current_color = peek_color;
-                    let ansi_value = current_color.editor_syntax_to_color();
-                    print!("{}{}{}", style::Reset, color::Fg(ansi_value), c)
-                } else {
-                    let ansi_value = current_color.editor_syntax_to_color();
-                    print!("{}{}", color::Fg(ansi_value), c);
+                let mut peek_style = self.theme.highlight_style(row.highlight[j]);
+                match self.increment_find.match_at(file_row, j) {
+                    Some(true) => {
+                        peek_style =
+                            peek_style.overlaid(self.theme.ui_style(UiElement::SearchCurrent))
+                    }
+                    Some(false) => {
+                        peek_style =
+                            peek_style.overlaid(self.theme.ui_style(UiElement::SearchMatch))
+                    }
+                    None => {}
+                }
+                if brackets.contains(&j) {
+                    peek_style =
+                        peek_style.overlaid(self.theme.ui_style(UiElement::MatchingBracket));
+                }
+                if selection.is_some_and(|(from, to)| (from..to).contains(&j)) {
+                    peek_style = peek_style.overlaid(self.theme.ui_style(UiElement::Selection));
                 }
+                if cursors.contains(&j) {
+                    peek_style = peek_style.overlaid(self.theme.ui_style(UiElement::Cursor));
+                }
+                if current_style != Some(peek_style) {
+                    current_style = Some(peek_style);
+                    Terminal::set_style(&peek_style, self.color_support);
+                }
+                print!("{}", c);
             }
         }
+        // a cursor at the end of the row has no char to sit on.
+        let end = row.render.len();
+        if cursors.contains(&end)
+            && end >= self.offset.x
+            && end < self.offset.x + self.terminal.window_size_width as usize
+        {
+            Terminal::set_style(&self.theme.ui_style(UiElement::Cursor), self.color_support);
+            print!(" ");
+        }
     }
 
     fn editor_draw_rows(&mut self) {
         self.editor_update_row();
+        let brackets = match self.matching_bracket() {
+            Some((bracket, partner)) => vec![bracket, partner],
+            None => vec![],
+        };
         for i in 0..self.terminal.window_size_height {
             let file_row = i as usize + self.offset.y;
             if file_row >= self.document.len() {
@@ -812,7 +2686,7 @@ impl Editor {
                 }
             } else {
                 if let Some(row) = self.document.row(file_row as usize) {
-                    self.draw_row(row)
+                    self.draw_row(file_row, row, &brackets)
                 }
             }
 
@@ -844,13 +2718,6 @@ impl Editor {
         }
     }
 
-    fn is_digit(c: &char) -> bool {
-        match *c {
-            '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => return true,
-            _ => return false,
-        }
-    }
-
     fn default_hilight(&mut self) {
         let mut highlight = vec![];
         for e_l in &self.document.rows {
@@ -866,220 +2733,31 @@ impl Editor {
         }
     }
 
-    fn single_comment_start_length(&self) -> usize {
-        match &self.editor_syntax {
-            Some(e_s) => return e_s.singleline_comment_start.chars().count(),
-            None => 0,
-        }
-    }
-
-    fn has_singleline_comment_started(&self, index: usize, render_row: &Vec<char>) -> bool {
-        let single_comment_start = match &self.editor_syntax {
-            Some(e_s) => e_s.singleline_comment_start.clone(),
-            None => String::from(""),
-        };
-        let mut s = String::new();
-        for (i, c) in render_row.iter().enumerate() {
-            if i >= index && i < index + self.single_comment_start_length() {
-                s.push(*c)
-            }
-        }
-
-        single_comment_start == s
-    }
-
-    fn is_separator(c: &char) -> bool {
-        match c {
-            ' ' => true,  // space
-            '\0' => true, // null,
-            ',' | '.' | '(' | ')' | '+' | '-' | '/' | '*' | '=' | '~' | '%' | '<' | '>' | '['
-            | ']' | ';' => true, // separator chars
-            _ => false,
-        }
-    }
-
-    fn get_word(&self, row: &str, start_index: usize) -> String {
-        let mut s = String::new();
-        for (i, c) in row.chars().enumerate() {
-            if i < start_index {
-                continue;
-            }
-
-            if Editor::is_separator(&c) {
-                break;
-            };
-
-            s.push(c);
-        }
-
-        s
-    }
-
-    fn multi_comment_start(&self) -> String {
-        match &self.editor_syntax {
-            Some(e_s) => return e_s.multiline_comment_start.clone(),
-            None => return String::new(),
-        }
-    }
-    fn multi_comment_end(&self) -> String {
-        match &self.editor_syntax {
-            Some(e_s) => return e_s.multiline_comment_end.clone(),
-            None => return String::new(),
-        }
-    }
-
-    fn multi_comment_end_len(&self) -> u16 {
-        match &self.editor_syntax {
-            Some(e_s) => return e_s.multiline_comment_end.chars().count() as u16,
-            None => return 0,
-        }
-    }
-
-    fn multi_comment_start_len(&self) -> u16 {
-        match &self.editor_syntax {
-            Some(e_s) => return e_s.multiline_comment_start.chars().count() as u16,
-            None => return 0,
-        }
-    }
-
-    fn str_compare(&self, row: &Vec<char>, start_index: usize, keyword: &String) -> bool {
-        let mut s = String::new();
-        for (i, c) in row.iter().enumerate() {
-            if i >= start_index && i < start_index + keyword.chars().count() {
-                s.push(*c)
-            }
-        }
-        keyword == &s
-    }
-
     fn editor_update_syntax(&mut self) {
-        if self.editor_syntax.is_none() {
-            self.default_hilight();
-            return;
-        }
-        let previous_separator = true;
-        let mut highlight_matrix = vec![];
-        let mut is_in_string: bool = false;
-        let mut in_string: char = '\0';
-        let mut is_in_comment: bool = false;
-
-        for (column_index, e_l) in self.document.rows.iter().enumerate() {
-            let mut highlight = vec![Highlight::Normal; e_l.render.len()];
-            let row = &e_l.render;
-            let mut row_index = 0;
-            let mut preivious_highlight = Highlight::Normal;
-
-            while row_index < e_l.render.len() {
-                let c = &row[row_index];
-
-                if row_index > 0 {
-                    preivious_highlight = highlight[row_index - 1];
-                }
-
-                // higlight single comment
-                if !is_in_string && !is_in_comment {
-                    if self.has_singleline_comment_started(row_index, &e_l.render) {
-                        while row_index < e_l.render.len() {
-                            highlight[row_index] = Highlight::Comment;
-                            row_index = row_index + 1
-                        }
-                        break;
-                    }
-                }
-
-                // higlight mult comment
-                if self.highlight_multi_comment() && !is_in_string {
-                    if is_in_comment {
-                        highlight[row_index] = Highlight::MultiComment;
-
-                        if self.str_compare(&row, row_index, &self.multi_comment_end()) {
-                            is_in_comment = false;
-                            for _ in 0..self.multi_comment_end_len() {
-                                highlight[row_index] = Highlight::MultiComment;
-                                row_index = row_index + 1;
-                            }
-                            continue;
-                        } else {
-                            row_index = row_index + 1;
-                            continue;
-                        }
-                    } else if self.str_compare(&row, row_index, &self.multi_comment_start()) {
-                        for _ in 0..self.multi_comment_start_len() {
-                            highlight[row_index] = Highlight::MultiComment;
-                            row_index = row_index + 1;
-                        }
-                        is_in_comment = true;
-                        continue;
-                    }
-                }
-
-                // higlight number
-                if self.highlight_numbers() {
-                    if Self::is_digit(c) || (*c == '.' && preivious_highlight == Highlight::Number)
-                    {
-                        highlight[row_index] = Highlight::Number;
-                        row_index = row_index + 1;
-                        continue;
-                    }
-                }
-
-                // highlight strings
-                if self.highlight_strings() {
-                    if is_in_string {
-                        highlight[row_index] = Highlight::String;
-
-                        if row_index > 0
-                            && e_l.render[row_index as usize - 1] == '\\'
-                            && row_index < self.get_current_row_render_length() as usize
-                        {
-                            row_index = row_index + 1;
-                            continue;
-                        }
-
-                        if *c == in_string {
-                            is_in_string = false;
-                        }
-                        row_index = row_index + 1;
-                        continue;
-                    } else {
-                        if *c == '"' || *c == '\'' {
-                            is_in_string = true;
-                            in_string = *c;
-                            highlight[row_index] = Highlight::String;
-
-                            row_index = row_index + 1;
-                            continue;
-                        }
-                    }
-                }
-                // hilight keywords
-                if previous_separator {
-                    let row = &self.document.row(column_index).unwrap().render_string();
-                    let word = &*self.get_word(row, row_index);
-                    if KEY_WORD_1.contains(&word) {
-                        for _ in 0..word.chars().count() {
-                            highlight[row_index] = Highlight::Keyword1;
-                            row_index = row_index + 1;
-                        }
-                        continue;
-                    }
-
-                    if KEY_WORD_2.contains(&word) {
-                        for _ in 0..word.chars().count() {
-                            highlight[row_index] = Highlight::Keyword2;
-                            row_index = row_index + 1;
-                        }
-                        continue;
-                    }
-                }
-                row_index = row_index + 1;
+        #[cfg(feature = "tree-sitter-highlight")]
+        {
+            if let Some(tree_sitter) = &mut self.tree_sitter {
+                let top = self.offset.y;
+                let visible = top..top + self.terminal.window_size_height as usize;
+                tree_sitter.update(&mut self.document, self.tab_stop, visible);
+                return;
             }
-
-            highlight_matrix.push(highlight);
         }
-        for i in 0..self.document.len() {
-            self.document
-                .replace_highlight(i as usize, highlight_matrix[i as usize].clone());
+        let syntax = match &self.editor_syntax {
+            Some(syntax) => syntax,
+            None => {
+                self.default_hilight();
+                return;
+            }
+        };
+        // each line starts in the state the previous one ended in, so only
+        // constructs that really span lines (block comments, raw strings,
+        // continued strings) carry over.
+        let mut state = LineState::Normal;
+        for row in self.document.rows.iter_mut() {
+            let (highlight, next_state) = highlight_line(syntax, &row.render, state);
+            row.highlight = highlight;
+            state = next_state;
         }
     }
 
@@ -1087,7 +2765,7 @@ impl Editor {
         let mut editor = Editor::new();
 
         editor.set_status_message(String::from(
-            "HELP: Ctr-S = save | Ctr-C = quit | Ctrl-F = find",
+            "HELP: Ctr-S = save | Ctr-C = quit | Ctrl-F = find | F3/F4 = next/prev | Ctrl-G = go to line | Ctrl-P = command",
         ));
 
         let args: Vec<String> = env::args().collect();
@@ -1096,12 +2774,31 @@ impl Editor {
             let document = Document::open(file_name).unwrap_or_default();
 
             editor.file_name = Some(String::from(file_name));
-            editor.editor_select_syntax_hilight();
             document
         } else {
             Document::default()
         };
         editor.document = document;
+        editor.editor_select_syntax_hilight();
+        editor.indent_style = IndentStyle::detect(&editor.document.rows, editor.tab_stop);
+        editor.search_history = History::load(SEARCH_HISTORY_FILE_NAME);
+        editor.command_history = History::load(COMMAND_HISTORY_FILE_NAME);
+        editor.registers = Registers::load();
+
+        let (config, mut errors) = Config::load();
+        let (macros, macro_errors) = Macros::load();
+        editor.macros = macros;
+        errors.extend(macro_errors);
+        errors.extend(editor.editor_set_editing_mode(&config.editing_mode));
+        editor.auto_pairs = config.auto_pairs;
+        editor.formatters = config.formatters;
+        match Theme::load(&config.theme) {
+            Ok(theme) => editor.theme = theme,
+            Err(e) => errors.push(e),
+        }
+        if !errors.is_empty() {
+            editor.set_status_message(errors.join(" | "));
+        }
 
         editor
     }
@@ -1125,6 +2822,143 @@ impl Editor {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_find_all() {
+        assert_eq!(find_all("abcabc", "bc"), vec![(1, 3), (4, 6)]);
+        assert_eq!(find_all("aaaa", "aa"), vec![(0, 2), (2, 4)]);
+        assert_eq!(find_all("日本語の本", "本"), vec![(1, 2), (4, 5)]);
+        assert!(find_all("abc", "x").is_empty());
+    }
+
+    #[test]
+    fn test_increment_find_steps_and_wraps() {
+        let rows = ["foo bar", "bar", "baz bar"]
+            .iter()
+            .map(|line| {
+                let mut row = Row::from(&String::from(*line));
+                row.render = row.buf.clone();
+                row
+            })
+            .collect::<Vec<Row>>();
+        let mut find = IncrementFind::new();
+        find.origin = (1, 1);
+        find.search("bar", &rows);
+        assert_eq!(find.matches.len(), 3);
+        assert_eq!(find.current_match(), Some((2, 4, 7)));
+        assert_eq!(find.summary(), Some(String::from("match 3 of 3")));
+        assert_eq!(find.match_at(2, 5), Some(true));
+        assert_eq!(find.match_at(0, 4), Some(false));
+        assert_eq!(find.match_at(0, 3), None);
+
+        find.step(IncrementFindDirection::Forward);
+        assert_eq!(find.current_match(), Some((0, 4, 7)));
+        find.step(IncrementFindDirection::Backward);
+        find.step(IncrementFindDirection::Backward);
+        assert_eq!(find.current_match(), Some((1, 0, 3)));
+
+        find.search("nope", &rows);
+        assert_eq!(find.summary(), Some(String::from("no matches")));
+    }
+
+    #[test]
+    fn test_multi_cursor_commands_exist() {
+        for name in MULTI_CURSOR_COMMANDS {
+            let command = command::find(name).map(|command| command.name);
+            assert_eq!(command, Some(*name), "`{}` is not a command", name);
+        }
+    }
+
+    #[test]
+    fn test_increment_find_after_mid_line_tab() {
+        let mut row = Row::from(&String::from("ab\tfoo"));
+        row.render = "ab    foo".chars().collect();
+        let rows = vec![row];
+        let mut find = IncrementFind::new();
+        find.search("foo", &rows);
+        let (y, column, _) = find.current_match().unwrap();
+        assert_eq!(column, 6);
+        assert_eq!(buffer_position(&rows, (y, column), 4), (3, 0));
+        // inside the tab.
+        assert_eq!(buffer_position(&rows, (0, 4), 4), (2, 0));
+        assert_eq!(buffer_position(&rows, (0, 20), 4), (6, 0));
+    }
+
+    #[test]
+    fn test_increment_find_origin_after_tab() {
+        let mut row = Row::from(&String::from("\tfoo foo"));
+        row.render = "    foo foo".chars().collect();
+        let rows = vec![row];
+        // the cursor on the first `foo`.
+        let mut find = IncrementFind::new();
+        find.origin = render_position(&rows, (2, 0), 4);
+        find.search("foo", &rows);
+        assert_eq!(find.current_match(), Some((0, 8, 11)));
+
+        find.origin = render_position(&rows, (1, 0), 4);
+        find.search("foo", &rows);
+        find.step(IncrementFindDirection::Backward);
+        assert_eq!(find.current_match(), Some((0, 8, 11)));
+        find.origin = render_position(&rows, (5, 0), 4);
+        find.search("foo", &rows);
+        find.step(IncrementFindDirection::Backward);
+        assert_eq!(find.current_match(), Some((0, 4, 7)));
+
+        // find-next from the first `foo` after a tab in the middle of a line.
+        let mut row = Row::from(&String::from("foo\tfoo"));
+        row.render = "foo    foo".chars().collect();
+        let rows = vec![row];
+        let mut find = IncrementFind::new();
+        find.origin = render_position(&rows, (1, 0), 4);
+        find.search("foo", &rows);
+        let (y, column, _) = find.current_match().unwrap();
+        assert_eq!(buffer_position(&rows, (y, column), 4), (4, 0));
+    }
+
+    fn editor_with(lines: &[&str]) -> Editor {
+        let mut editor = Editor::default();
+        for (i, line) in lines.iter().enumerate() {
+            editor
+                .document
+                .insert_row(i, Row::from(&String::from(*line)));
+        }
+        editor
+    }
+
+    fn text(editor: &Editor) -> Vec<String> {
+        let rows = editor.document.rows.iter();
+        rows.map(|row| row.buf.iter().collect()).collect()
+    }
+
+    #[test]
+    fn test_typing_ends_the_region() {
+        let mut editor = editor_with(&["ab cd"]);
+        editor.editor_set_syntax(Some(FileType::Rust));
+        editor.editor_run_command("set-mark");
+        editor.move_cursor(Key::Right);
+        editor.move_cursor(Key::Right);
+        editor.editor_insert_char('x');
+        editor.editor_insert_char('(');
+        assert_eq!(text(&editor), vec!["abx() cd"]);
+
+        // right after marking a region, an opener still wraps it.
+        editor.editor_run_command("set-mark");
+        editor.move_cursor(Key::Left);
+        editor.editor_insert_char('[');
+        assert_eq!(text(&editor), vec!["abx[(]) cd"]);
+    }
+
+    #[test]
+    fn test_vim_backspace_and_delete() {
+        let mut editor = editor_with(&["abc"]);
+        editor.editor_set_editing_mode("vim");
+        editor.position.x = 2;
+        editor.vim_key(Key::Backspace);
+        assert_eq!(text(&editor), vec!["abc"]);
+        assert_eq!(editor.position.x, 1);
+        editor.vim_key(Key::Delete);
+        assert_eq!(text(&editor), vec!["ac"]);
+    }
+
     fn open_test_file() -> Document {
         let file_name = "./tests/test.txt";
         let document = Document::open(file_name);
@@ -0,0 +1,138 @@
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long the editor lets a shell command run before killing it.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a command that exited successfully wrote.
+#[derive(Debug, PartialEq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+fn read_all(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+/// The first line of `stderr` that is not blank, for the message bar.
+pub fn first_line(stderr: &str) -> &str {
+    stderr.trim().lines().next().unwrap_or("")
+}
+
+// kills `child` and everything it started, which may still hold its pipes
+// open. `run` puts the child in a process group of its own, so the group id
+// is the child's pid.
+fn kill_group(child: &Child) {
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+fn wait(child: &mut Child, timeout: Duration) -> Result<Option<i32>, String> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            return Ok(Some(status.code().unwrap_or(-1)));
+        }
+        if start.elapsed() >= timeout {
+            kill_group(child);
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Runs `command` with `sh -c`, writing `input` to its stdin. The command
+/// is killed when it takes longer than `timeout`, and a non-zero exit
+/// status is an error along with the first line of its stderr.
+pub fn run(command: &str, input: &str, timeout: Duration) -> Result<Output, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("Could not run `{}`: {}", command, e))?;
+    let mut stdin = child.stdin.take();
+    let input = String::from(input);
+    // a command that never reads its input must not block the editor.
+    thread::spawn(move || {
+        if let Some(stdin) = &mut stdin {
+            let _ = stdin.write_all(input.as_bytes());
+        }
+    });
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+    let code = wait(&mut child, timeout);
+    // whatever the shell left running in the background goes too, so the
+    // pipes close and the readers finish.
+    kill_group(&child);
+    let output = Output {
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+    let code = match code? {
+        Some(code) => code,
+        None => {
+            return Err(format!(
+                "`{}` took longer than {}s and was killed",
+                command,
+                timeout.as_secs_f32()
+            ))
+        }
+    };
+    if code != 0 {
+        return Err(format!(
+            "`{}` exited with status {}: {}",
+            command,
+            code,
+            first_line(&output.stderr)
+        ));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            run("tr a-z A-Z; echo done >&2", "abc\n", timeout),
+            Ok(Output {
+                stdout: String::from("ABC\n"),
+                stderr: String::from("done\n"),
+            })
+        );
+        assert_eq!(
+            run("cat >/dev/null; echo oops >&2; exit 3", "abc", timeout),
+            Err(String::from(
+                "`cat >/dev/null; echo oops >&2; exit 3` exited with status 3: oops"
+            ))
+        );
+        let start = Instant::now();
+        assert!(run("sleep 5", "", Duration::from_millis(100)).is_err());
+        // the shell's children are killed with it and the pipes close.
+        assert!(run("(sleep 5; echo late) | cat", "", Duration::from_millis(100)).is_err());
+        assert_eq!(
+            run("sleep 5 & echo now", "", timeout).map(|output| output.stdout),
+            Ok(String::from("now\n"))
+        );
+        assert!(start.elapsed() < timeout);
+    }
+}
@@ -0,0 +1,68 @@
+// the number of non-whitespace chars in `old` before `cursor`, and
+// whether the cursor is on one.
+fn text_before(old: &str, cursor: (usize, usize)) -> (usize, bool) {
+    let mut before = 0;
+    for (y, line) in old.lines().enumerate().take(cursor.1 + 1) {
+        let chars = line.chars().collect::<Vec<char>>();
+        let end = if y == cursor.1 {
+            cursor.0.min(chars.len())
+        } else {
+            chars.len()
+        };
+        before += chars[..end].iter().filter(|c| !c.is_whitespace()).count();
+        if y == cursor.1 {
+            let on_text = chars.get(cursor.0).is_some_and(|c| !c.is_whitespace());
+            return (before, on_text);
+        }
+    }
+    (before, false)
+}
+
+/// Where `cursor` in `old` belongs in `new`, which is `old` after a
+/// formatter has mostly changed its whitespace: on the same char of text
+/// when it was on one, or else just after the text before it.
+pub fn moved_cursor(old: &str, new: &str, cursor: (usize, usize)) -> (usize, usize) {
+    let (before, on_text) = text_before(old, cursor);
+    if before == 0 && !on_text {
+        return (0, 0);
+    }
+    let mut seen = 0;
+    let mut end = (0, 0);
+    for (y, line) in new.lines().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if on_text && seen == before {
+                return (x, y);
+            }
+            seen += 1;
+            end = (x + 1, y);
+            if !on_text && seen == before {
+                return end;
+            }
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_moved_cursor() {
+        let old = "fn f(){\n  let x=1;  \n}\n";
+        let new = "fn f() {\n    let x = 1;\n}\n";
+        // on the `=`.
+        assert_eq!(moved_cursor(old, new, (7, 1)), (10, 1));
+        // on the `l` of `let`.
+        assert_eq!(moved_cursor(old, new, (2, 1)), (4, 1));
+        // in the indentation before `let`, so just after the `{`.
+        assert_eq!(moved_cursor(old, new, (1, 1)), (8, 0));
+        // after the `;`.
+        assert_eq!(moved_cursor(old, new, (10, 1)), (14, 1));
+        assert_eq!(moved_cursor(old, "", (1, 2)), (0, 0));
+        assert_eq!(moved_cursor("", new, (0, 0)), (0, 0));
+    }
+}
@@ -1,28 +1,68 @@
-use termion::*;
-
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Highlight {
     Normal,
     Number,
-    Match,
     String,
     Comment,
     MultiComment,
+    DocComment,
     Keyword1,
     Keyword2,
+    Function,
+    Type,
+    Constant,
+    Operator,
+    Preprocessor,
+    Escape,
+    Todo,
+    Diagnostic,
 }
 
 impl Highlight {
-    pub fn editor_syntax_to_color(self) -> termion::color::AnsiValue {
+    pub fn all() -> [Highlight; 16] {
+        [
+            Highlight::Normal,
+            Highlight::Number,
+            Highlight::String,
+            Highlight::Comment,
+            Highlight::MultiComment,
+            Highlight::DocComment,
+            Highlight::Keyword1,
+            Highlight::Keyword2,
+            Highlight::Function,
+            Highlight::Type,
+            Highlight::Constant,
+            Highlight::Operator,
+            Highlight::Preprocessor,
+            Highlight::Escape,
+            Highlight::Todo,
+            Highlight::Diagnostic,
+        ]
+    }
+
+    /// The name used for this class in theme files.
+    pub fn name(self) -> &'static str {
         match self {
-            Highlight::Normal => color::AnsiValue(7), // White
-            Highlight::Number => color::AnsiValue(1), // Red
-            Highlight::Match => color::AnsiValue(4),  // Blue
-            Highlight::String => color::AnsiValue(5), // Magenta
-            Highlight::Comment => color::AnsiValue(6), // Cyan
-            Highlight::MultiComment => color::AnsiValue(6), // Cyan
-            Highlight::Keyword1 => color::AnsiValue(2), // Green
-            Highlight::Keyword2 => color::AnsiValue(3), // Yellow
+            Highlight::Normal => "normal",
+            Highlight::Number => "number",
+            Highlight::String => "string",
+            Highlight::Comment => "comment",
+            Highlight::MultiComment => "multi_comment",
+            Highlight::DocComment => "doc_comment",
+            Highlight::Keyword1 => "keyword1",
+            Highlight::Keyword2 => "keyword2",
+            Highlight::Function => "function",
+            Highlight::Type => "type",
+            Highlight::Constant => "constant",
+            Highlight::Operator => "operator",
+            Highlight::Preprocessor => "preprocessor",
+            Highlight::Escape => "escape",
+            Highlight::Todo => "todo",
+            Highlight::Diagnostic => "diagnostic",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Highlight> {
+        Highlight::all().iter().copied().find(|h| h.name() == name)
+    }
 }
@@ -0,0 +1,851 @@
+use crate::syntax::{EditorSyntax, Preprocessor};
+use crate::Highlight;
+
+const OPERATOR_CHARS: &str = "+-*/%=<>!&|^~?";
+
+// markers picked out inside comments.
+const TODO_MARKERS: [&str; 4] = ["TODO", "FIXME", "XXX", "HACK"];
+
+/// The lexer state a line leaves open for the next one.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum LineState {
+    #[default]
+    Normal,
+    /// inside a block comment, with its nesting depth.
+    BlockComment { depth: usize, doc: bool },
+    /// inside a string that runs over the end of the line. In a `raw` one
+    /// backslashes are still skipped over but not shown as escapes.
+    String { quote: char, raw: bool },
+    /// inside a Rust raw string, with the number of `#`s that close it.
+    RawString(usize),
+    /// inside a `"""` or `'''` string, `raw` as for `String`.
+    TripleString { quote: char, raw: bool },
+}
+
+fn comment_class(doc: bool) -> Highlight {
+    if doc {
+        Highlight::DocComment
+    } else {
+        Highlight::MultiComment
+    }
+}
+
+pub fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == '\0' || !(c.is_alphanumeric() || c == '_')
+}
+
+/// Highlights a single rendered line, starting in `state` (the state the
+/// previous line ended in), and returns the state this line ends in.
+pub fn highlight_line(
+    syntax: &EditorSyntax,
+    line: &[char],
+    state: LineState,
+) -> (Vec<Highlight>, LineState) {
+    let mut scanner = Scanner {
+        syntax,
+        line,
+        highlight: vec![Highlight::Normal; line.len()],
+        index: 0,
+    };
+    let state = scanner.scan(state);
+    (scanner.highlight, state)
+}
+
+struct Scanner<'a> {
+    syntax: &'a EditorSyntax,
+    line: &'a [char],
+    highlight: Vec<Highlight>,
+    index: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn scan(&mut self, mut state: LineState) -> LineState {
+        let mut previous_separator = true;
+
+        while self.index < self.line.len() {
+            match state {
+                LineState::Normal => {}
+                LineState::BlockComment { depth, doc } => {
+                    state = self.block_comment(depth, doc);
+                    previous_separator = true;
+                    continue;
+                }
+                LineState::String { quote, raw } => {
+                    state = self.string(quote, raw, None);
+                    previous_separator = true;
+                    continue;
+                }
+                LineState::RawString(hashes) => {
+                    state = self.raw_string(hashes);
+                    previous_separator = true;
+                    continue;
+                }
+                LineState::TripleString { quote, raw } => {
+                    state = self.triple_string(quote, raw);
+                    previous_separator = true;
+                    continue;
+                }
+            }
+
+            let c = self.line[self.index];
+
+            if self.is_line_comment_start() {
+                let class = if self.is_doc_comment_start('/', '/') {
+                    Highlight::DocComment
+                } else {
+                    Highlight::Comment
+                };
+                self.fill(self.index, self.line.len(), class);
+                self.index = self.line.len();
+                break;
+            }
+
+            let block_start = &self.syntax.multiline_comment_start;
+            if !block_start.is_empty() && self.starts_with(self.index, block_start) {
+                let len = block_start.chars().count();
+                let doc = self.is_doc_comment_start('*', '/');
+                self.fill(self.index, self.index + len, comment_class(doc));
+                self.index += len;
+                state = LineState::BlockComment { depth: 1, doc };
+                continue;
+            }
+
+            if previous_separator && self.preprocessor() {
+                continue;
+            }
+
+            if self.syntax.highlight_strings {
+                if let Some(next) = self.string_start(previous_separator) {
+                    state = next;
+                    previous_separator = true;
+                    continue;
+                }
+            }
+
+            if self.syntax.highlight_number && previous_separator && self.is_number_start() {
+                self.number();
+                previous_separator = false;
+                continue;
+            }
+
+            if previous_separator && self.keyword() {
+                previous_separator = false;
+                continue;
+            }
+
+            if !is_separator(c) {
+                self.identifier();
+                previous_separator = false;
+                continue;
+            }
+
+            if self.syntax.highlight_operators && OPERATOR_CHARS.contains(c) {
+                self.highlight[self.index] = Highlight::Operator;
+            }
+            previous_separator = true;
+            self.index += 1;
+        }
+
+        self.mark_todos();
+        state
+    }
+
+    fn starts_with(&self, index: usize, pattern: &str) -> bool {
+        let len = pattern.chars().count();
+        index + len <= self.line.len()
+            && self.line[index..index + len]
+                .iter()
+                .copied()
+                .eq(pattern.chars())
+    }
+
+    fn peek(&self, ahead: usize) -> Option<char> {
+        self.line.get(self.index + ahead).copied()
+    }
+
+    fn fill(&mut self, start: usize, end: usize, highlight: Highlight) {
+        let end = end.min(self.highlight.len());
+        for h in &mut self.highlight[start..end] {
+            *h = highlight;
+        }
+    }
+
+    fn is_line_comment_start(&self) -> bool {
+        match &self.syntax.singleline_comment_start {
+            Some(start) if self.starts_with(self.index, start) => {}
+            _ => return false,
+        }
+        !self.syntax.comment_needs_separator
+            || self.index == 0
+            || self.line[self.index - 1].is_whitespace()
+    }
+
+    // `///` and `/**` open doc comments, `//!` and `/*!` inner doc comments,
+    // but `////` and `/**/` are plain ones.
+    fn is_doc_comment_start(&self, third: char, not_fourth: char) -> bool {
+        if !self.syntax.doc_comments {
+            return false;
+        }
+        match self.peek(2) {
+            Some('!') => true,
+            Some(c) if c == third => self.peek(3) != Some(not_fourth),
+            _ => false,
+        }
+    }
+
+    fn block_comment(&mut self, mut depth: usize, doc: bool) -> LineState {
+        let class = comment_class(doc);
+        let start = &self.syntax.multiline_comment_start;
+        let end = &self.syntax.multiline_comment_end;
+        let (start_len, end_len) = (start.chars().count(), end.chars().count());
+
+        while self.index < self.line.len() {
+            if self.starts_with(self.index, end) {
+                self.fill(self.index, self.index + end_len, class);
+                self.index += end_len;
+                depth -= 1;
+                if depth == 0 {
+                    return LineState::Normal;
+                }
+            } else if self.syntax.nested_comments && self.starts_with(self.index, start) {
+                self.fill(self.index, self.index + start_len, class);
+                self.index += start_len;
+                depth += 1;
+            } else {
+                self.highlight[self.index] = class;
+                self.index += 1;
+            }
+        }
+        LineState::BlockComment { depth, doc }
+    }
+
+    fn preprocessor(&mut self) -> bool {
+        let start = self.index;
+        let at_line_start = self.line[..start].iter().all(|c| c.is_whitespace());
+        match self.syntax.preprocessor {
+            Preprocessor::Directive if at_line_start && self.peek(0) == Some('#') => {
+                self.index += 1;
+                while self.peek(0).is_some_and(|c| c == ' ' || c == '\t') {
+                    self.index += 1;
+                }
+                let word_start = self.index;
+                while self.peek(0).is_some_and(|c| !is_separator(c)) {
+                    self.index += 1;
+                }
+                self.fill(start, self.index, Highlight::Preprocessor);
+                let is_include = self.line[word_start..self.index]
+                    .iter()
+                    .copied()
+                    .eq("include".chars());
+                while is_include && self.peek(0).is_some_and(|c| c == ' ' || c == '\t') {
+                    self.index += 1;
+                }
+                if is_include && self.peek(0) == Some('<') {
+                    let header_start = self.index;
+                    while self.peek(0).is_some_and(|c| c != '>') {
+                        self.index += 1;
+                    }
+                    self.index = (self.index + 1).min(self.line.len());
+                    self.fill(header_start, self.index, Highlight::String);
+                }
+                true
+            }
+            Preprocessor::Attribute
+                if self.peek(0) == Some('#')
+                    && (self.peek(1) == Some('[')
+                        || (self.peek(1) == Some('!') && self.peek(2) == Some('['))) =>
+            {
+                let mut depth = 0;
+                while let Some(c) = self.peek(0) {
+                    self.index += 1;
+                    match c {
+                        '[' => depth += 1,
+                        ']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                self.fill(start, self.index, Highlight::Preprocessor);
+                true
+            }
+            Preprocessor::Decorator if at_line_start && self.peek(0) == Some('@') => {
+                self.index += 1;
+                while self.peek(0).is_some_and(|c| !is_separator(c) || c == '.') {
+                    self.index += 1;
+                }
+                self.fill(start, self.index, Highlight::Preprocessor);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn identifier(&mut self) {
+        let start = self.index;
+        while self.index < self.line.len() && !is_separator(self.line[self.index]) {
+            self.index += 1;
+        }
+        let word = self.line[start..self.index].iter().collect::<String>();
+        let first = self.line[start];
+        let is_macro =
+            self.peek(0) == Some('!') && matches!(self.peek(1), Some('(') | Some('[') | Some('{'));
+
+        let class = if self.syntax.constants.contains(&word.as_str()) {
+            Highlight::Constant
+        } else if self.syntax.identifier_heuristics
+            && first.is_uppercase()
+            && word.chars().any(|c| c.is_lowercase())
+        {
+            Highlight::Type
+        } else if self.peek(0) == Some('(') || (self.syntax.char_literals && is_macro) {
+            if is_macro {
+                self.index += 1;
+            }
+            Highlight::Function
+        } else if self.syntax.identifier_heuristics && first.is_uppercase() {
+            if word.chars().count() == 1 {
+                // single letters are generic parameters more often than not.
+                Highlight::Type
+            } else {
+                Highlight::Constant
+            }
+        } else if self.syntax.identifier_heuristics && word.ends_with("_t") {
+            Highlight::Type
+        } else {
+            Highlight::Normal
+        };
+        self.fill(start, self.index, class);
+    }
+
+    fn mark_todos(&mut self) {
+        for i in 0..self.line.len() {
+            let in_comment = matches!(
+                self.highlight[i],
+                Highlight::Comment | Highlight::MultiComment | Highlight::DocComment
+            );
+            if !in_comment || (i > 0 && !is_separator(self.line[i - 1])) {
+                continue;
+            }
+            for marker in TODO_MARKERS.iter() {
+                let end = i + marker.len();
+                if self.starts_with(i, marker)
+                    && (end == self.line.len() || is_separator(self.line[end]))
+                {
+                    self.fill(i, end, Highlight::Todo);
+                }
+            }
+        }
+    }
+
+    /// Recognises the opening of any kind of string or char literal at the
+    /// current index, including its prefix, and scans as far as it goes.
+    fn string_start(&mut self, previous_separator: bool) -> Option<LineState> {
+        let start = self.index;
+        let mut i = self.index;
+        let mut raw = false;
+
+        if previous_separator {
+            // at most two prefix letters, e.g. `br"..."` or `Rb'...'`.
+            while i < self.line.len() && i < start + 2 {
+                let c = self.line[i];
+                if self.syntax.raw_prefixes.contains(c) {
+                    raw = true;
+                } else if !self.syntax.string_prefixes.contains(c) {
+                    break;
+                }
+                i += 1;
+            }
+        }
+
+        if raw && self.syntax.hashed_raw_strings {
+            let mut hashes = 0;
+            while i + hashes < self.line.len() && self.line[i + hashes] == '#' {
+                hashes += 1;
+            }
+            if self.line.get(i + hashes) != Some(&'"') {
+                return None;
+            }
+            self.index = i + hashes + 1;
+            self.fill(start, self.index, Highlight::String);
+            return Some(self.raw_string(hashes));
+        }
+
+        let quote = *self.line.get(i)?;
+        if self.syntax.char_literals && quote == '\'' {
+            if i > start && !self.syntax.string_prefixes.contains(self.line[start]) {
+                return None;
+            }
+            return self.char_literal(start, i);
+        }
+        if !self.syntax.string_quotes.contains(&quote) {
+            return None;
+        }
+
+        if self.syntax.triple_quotes
+            && self.line.get(i + 1) == Some(&quote)
+            && self.line.get(i + 2) == Some(&quote)
+        {
+            self.index = i + 3;
+            self.fill(start, self.index, Highlight::String);
+            return Some(self.triple_string(quote, raw));
+        }
+
+        self.index = i + 1;
+        self.fill(start, self.index, Highlight::String);
+        Some(self.string(quote, raw, Some(i)))
+    }
+
+    // `'a'`, `'\n'`, `b'\x7f'` are literals; `'a` and `'static` are lifetimes.
+    fn char_literal(&mut self, start: usize, quote_index: usize) -> Option<LineState> {
+        let end = match self.line.get(quote_index + 1) {
+            Some('\\') => {
+                let mut j = quote_index + 2;
+                while j < self.line.len() && self.line[j] != '\'' {
+                    j += 1;
+                }
+                if j >= self.line.len() {
+                    return None;
+                }
+                j + 1
+            }
+            Some(_) if self.line.get(quote_index + 2) == Some(&'\'') => quote_index + 3,
+            _ => return None,
+        };
+        self.fill(start, end, Highlight::String);
+        if self.line[quote_index + 1] == '\\' {
+            self.fill(quote_index + 1, end - 1, Highlight::Escape);
+        }
+        self.index = end;
+        Some(LineState::Normal)
+    }
+
+    // `open` is where the opening quote sits, when it is on this line.
+    fn string(&mut self, quote: char, raw: bool, open: Option<usize>) -> LineState {
+        let escapes = !self.syntax.raw_quotes.contains(&quote);
+        while self.index < self.line.len() {
+            let c = self.line[self.index];
+            self.highlight[self.index] = Highlight::String;
+            if c == '\\' && escapes {
+                if self.index + 1 == self.line.len() {
+                    // a trailing backslash continues the string on the next line.
+                    self.index += 1;
+                    return LineState::String { quote, raw };
+                }
+                self.escape(raw);
+                continue;
+            }
+            self.index += 1;
+            if c == quote {
+                return LineState::Normal;
+            }
+        }
+        if self.syntax.multiline_strings {
+            return LineState::String { quote, raw };
+        }
+        if let Some(open) = open {
+            self.highlight[open] = Highlight::Diagnostic;
+        }
+        LineState::Normal
+    }
+
+    /// Consumes the escape sequence at the backslash under the index.
+    fn escape(&mut self, raw: bool) {
+        let hex_digits = |scanner: &Self, from: usize, max: usize| {
+            (from..from + max)
+                .take_while(|i| scanner.line.get(*i).is_some_and(|c| c.is_ascii_hexdigit()))
+                .count()
+        };
+        let len = match self.peek(1) {
+            Some('x') => 2 + hex_digits(self, self.index + 2, 2),
+            Some('u') if self.peek(2) == Some('{') => {
+                match self.line[self.index..].iter().position(|c| *c == '}') {
+                    Some(close) => close + 1,
+                    None => 2,
+                }
+            }
+            Some('u') => 2 + hex_digits(self, self.index + 2, 4),
+            Some('U') => 2 + hex_digits(self, self.index + 2, 8),
+            Some('0'..='7') => {
+                1 + (self.index + 1..self.index + 4)
+                    .take_while(|i| self.line.get(*i).is_some_and(|c| ('0'..='7').contains(c)))
+                    .count()
+            }
+            _ => 2,
+        };
+        let end = (self.index + len).min(self.line.len());
+        let class = if raw {
+            Highlight::String
+        } else {
+            Highlight::Escape
+        };
+        self.fill(self.index, end, class);
+        self.index = end;
+    }
+
+    fn raw_string(&mut self, hashes: usize) -> LineState {
+        while self.index < self.line.len() {
+            let c = self.line[self.index];
+            self.highlight[self.index] = Highlight::String;
+            self.index += 1;
+            if c == '"' && (0..hashes).all(|h| self.line.get(self.index + h) == Some(&'#')) {
+                self.fill(self.index, self.index + hashes, Highlight::String);
+                self.index += hashes;
+                return LineState::Normal;
+            }
+        }
+        LineState::RawString(hashes)
+    }
+
+    fn triple_string(&mut self, quote: char, raw: bool) -> LineState {
+        while self.index < self.line.len() {
+            let c = self.line[self.index];
+            self.highlight[self.index] = Highlight::String;
+            if c == '\\' && self.index + 1 < self.line.len() {
+                self.escape(raw);
+                continue;
+            }
+            self.index += 1;
+            if c == quote && self.peek(0) == Some(quote) && self.peek(1) == Some(quote) {
+                self.fill(self.index, self.index + 2, Highlight::String);
+                self.index += 2;
+                return LineState::Normal;
+            }
+        }
+        LineState::TripleString { quote, raw }
+    }
+
+    fn is_number_start(&self) -> bool {
+        match self.peek(0) {
+            Some(c) if c.is_ascii_digit() => true,
+            // `.5`, but not the `.10` in `0..10`.
+            Some('.') => {
+                self.peek(1).is_some_and(|c| c.is_ascii_digit())
+                    && (self.index == 0 || self.line[self.index - 1] != '.')
+            }
+            _ => false,
+        }
+    }
+
+    // 42, 0x2A, 0b1010, 0o52, 1_000, 3.14, .5, 1e-9, 2.5E+3f, 10u32, 7ULL
+    fn number(&mut self) {
+        let start = self.index;
+        let radix_prefix = self.peek(0) == Some('0')
+            && matches!(
+                self.peek(1),
+                Some('x') | Some('X') | Some('b') | Some('B') | Some('o') | Some('O')
+            )
+            && self.peek(2).is_some_and(|c| c.is_ascii_hexdigit());
+
+        if radix_prefix {
+            self.index += 2;
+            while self
+                .peek(0)
+                .is_some_and(|c| c.is_ascii_hexdigit() || c == '_')
+            {
+                self.index += 1;
+            }
+        } else {
+            self.digits();
+            // `1.5` and `1.` are floats, but not `1..2` or `1.max(2)`.
+            if self.peek(0) == Some('.')
+                && self
+                    .peek(1)
+                    .is_none_or(|c| c != '.' && !c.is_alphabetic() && c != '_')
+            {
+                self.index += 1;
+                self.digits();
+            }
+            let exponent_digit = match self.peek(1) {
+                Some('+') | Some('-') => self.peek(2),
+                next => next,
+            };
+            if matches!(self.peek(0), Some('e') | Some('E'))
+                && exponent_digit.is_some_and(|c| c.is_ascii_digit())
+            {
+                self.index += 1;
+                if matches!(self.peek(0), Some('+') | Some('-')) {
+                    self.index += 1;
+                }
+                self.digits();
+            }
+        }
+        // type suffixes such as `u32`, `f64`, `ULL` or `j`.
+        while self
+            .peek(0)
+            .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        {
+            self.index += 1;
+        }
+        self.fill(start, self.index, Highlight::Number);
+    }
+
+    fn digits(&mut self) {
+        while self.peek(0).is_some_and(|c| c.is_ascii_digit() || c == '_') {
+            self.index += 1;
+        }
+    }
+
+    fn keyword(&mut self) -> bool {
+        let classes = [
+            (self.syntax.keywords1, Highlight::Keyword1),
+            (self.syntax.keywords2, Highlight::Keyword2),
+        ];
+        for (keywords, highlight) in classes.iter() {
+            for keyword in keywords.iter() {
+                let len = keyword.chars().count();
+                let end = self.index + len;
+                if self.starts_with(self.index, keyword)
+                    && (end == self.line.len() || is_separator(self.line[end]))
+                {
+                    self.fill(self.index, end, *highlight);
+                    self.index = end;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::FileType;
+
+    // renders highlights as one letter per char so expectations stay readable.
+    fn classes(file_type: FileType, line: &str, state: LineState) -> (String, LineState) {
+        let syntax = EditorSyntax::new(file_type);
+        let chars = line.chars().collect::<Vec<char>>();
+        let (highlight, state) = highlight_line(&syntax, &chars, state);
+        let letters = highlight
+            .iter()
+            .map(|h| match h {
+                Highlight::Normal => '.',
+                Highlight::Number => 'n',
+                Highlight::String => 's',
+                Highlight::Comment => 'c',
+                Highlight::MultiComment => 'C',
+                Highlight::DocComment => 'd',
+                Highlight::Keyword1 => 'k',
+                Highlight::Keyword2 => 't',
+                Highlight::Function => 'f',
+                Highlight::Type => 'T',
+                Highlight::Constant => 'K',
+                Highlight::Operator => 'o',
+                Highlight::Preprocessor => 'p',
+                Highlight::Escape => 'e',
+                Highlight::Todo => 'x',
+                Highlight::Diagnostic => '!',
+            })
+            .collect();
+        (letters, state)
+    }
+
+    fn line(file_type: FileType, line: &str) -> String {
+        classes(file_type, line, LineState::Normal).0
+    }
+
+    #[test]
+    fn test_c() {
+        assert_eq!(line(FileType::C, "int x = 42;"), "ttt...o.nn.");
+        assert_eq!(line(FileType::C, "printf(x);"), "ffffff....");
+        assert_eq!(line(FileType::C, "elsewhere;"), "..........");
+        assert_eq!(line(FileType::C, "a = \"\\\"\" + 1;"), "..o.sees.o.n.");
+        assert_eq!(line(FileType::C, "c = '\\'';"), "..o.sees.");
+        assert_eq!(line(FileType::C, "x = 0xFFul;"), "..o.nnnnnn.");
+        assert_eq!(line(FileType::C, "f = 1.5e-3f;"), "..o.nnnnnnn.");
+        assert_eq!(line(FileType::C, "abc1 = 2; // x"), ".....o.n..cccc");
+
+        let (highlight, state) = classes(FileType::C, "x /* a", LineState::Normal);
+        assert_eq!(highlight, "..CCCC");
+        assert_eq!(
+            state,
+            LineState::BlockComment {
+                depth: 1,
+                doc: false
+            }
+        );
+        let (highlight, state) = classes(FileType::C, "b */ if", state);
+        assert_eq!(highlight, "CCCC.kk");
+        assert_eq!(state, LineState::Normal);
+    }
+
+    #[test]
+    fn test_c_strings_do_not_leak_across_lines() {
+        let (highlight, state) = classes(FileType::C, "x = \"oops", LineState::Normal);
+        assert_eq!(highlight, "..o.!ssss");
+        assert_eq!(state, LineState::Normal);
+        let (_, state) = classes(FileType::C, "s = \"cont\\", LineState::Normal);
+        assert_eq!(
+            state,
+            LineState::String {
+                quote: '"',
+                raw: false
+            }
+        );
+        let (highlight, state) = classes(FileType::C, "inued\"; if", state);
+        assert_eq!(highlight, "ssssss..kk");
+        assert_eq!(state, LineState::Normal);
+    }
+
+    #[test]
+    fn test_rust() {
+        assert_eq!(
+            line(FileType::Rust, "fn f<'a>(x: &'a str)"),
+            "kk..o..o....o...ttt."
+        );
+        assert_eq!(line(FileType::Rust, "let c = 'x';"), "kkk...o.sss.");
+        assert_eq!(line(FileType::Rust, "let c = b'\\n';"), "kkk...o.ssees.");
+        assert_eq!(
+            line(FileType::Rust, "let r = r#\"a\"b\"#;"),
+            "kkk...o.ssssssss."
+        );
+        assert_eq!(line(FileType::Rust, "for i in 0..10 {"), "kkk...kk.n..nn..");
+        assert_eq!(line(FileType::Rust, "1_000u64.max(2)"), "nnnnnnnn.fff.n.");
+        assert_eq!(line(FileType::Rust, "let iffy = 1;"), "kkk......o.n.");
+
+        let (highlight, state) = classes(FileType::Rust, "/* a /* b */ c", LineState::Normal);
+        assert_eq!(highlight, "CCCCCCCCCCCCCC");
+        assert_eq!(
+            state,
+            LineState::BlockComment {
+                depth: 1,
+                doc: false
+            }
+        );
+
+        let (_, state) = classes(FileType::Rust, "let s = \"multi", LineState::Normal);
+        assert_eq!(
+            state,
+            LineState::String {
+                quote: '"',
+                raw: false
+            }
+        );
+        let (_, state) = classes(FileType::Rust, "r#\"raw \"still\"", LineState::Normal);
+        assert_eq!(state, LineState::RawString(1));
+        let (highlight, state) = classes(FileType::Rust, "end\"# fn", state);
+        assert_eq!(highlight, "sssss.kk");
+        assert_eq!(state, LineState::Normal);
+    }
+
+    #[test]
+    fn test_python() {
+        assert_eq!(
+            line(FileType::Python, "def f(x): return 1.5"),
+            "kkk.f.....kkkkkk.nnn"
+        );
+        assert_eq!(line(FileType::Python, "s = rb'\\d' # c"), "..o.ssssss.ccc");
+        assert_eq!(line(FileType::Python, "x = f\"{y}\""), "..o.ssssss");
+        assert_eq!(line(FileType::Python, "z = 1e10j"), "..o.nnnnn");
+
+        let (highlight, state) = classes(FileType::Python, "d = \"\"\"doc", LineState::Normal);
+        assert_eq!(highlight, "..o.ssssss");
+        assert_eq!(
+            state,
+            LineState::TripleString {
+                quote: '"',
+                raw: false
+            }
+        );
+        let (highlight, state) = classes(FileType::Python, "it's \"\"\" if", state);
+        assert_eq!(highlight, "ssssssss.kk");
+        assert_eq!(state, LineState::Normal);
+
+        // the next line of a raw string has no escapes either.
+        let (highlight, state) = classes(FileType::Python, "p = r'''\\d", LineState::Normal);
+        assert_eq!(highlight, "..o.ssssss");
+        assert_eq!(
+            state,
+            LineState::TripleString {
+                quote: '\'',
+                raw: true
+            }
+        );
+        let (highlight, _) = classes(FileType::Python, "\\w+'''", state);
+        assert_eq!(highlight, "ssssss");
+    }
+
+    #[test]
+    fn test_shell() {
+        assert_eq!(line(FileType::Shell, "echo $# # count"), "tttt....ccccccc");
+        assert_eq!(line(FileType::Shell, "x='a\\' # c"), "..ssss.ccc");
+        assert_eq!(
+            line(FileType::Shell, "if [ -n \"$x\" ]; then"),
+            "kk......ssss....kkkk"
+        );
+        assert_eq!(line(FileType::Shell, "file2=3"), "......n");
+    }
+
+    #[test]
+    fn test_makefile_and_dockerfile() {
+        assert_eq!(
+            line(FileType::Makefile, ".PHONY: all # x"),
+            "tttttt......ccc"
+        );
+        assert_eq!(line(FileType::Makefile, "v1 := 10"), "........");
+        assert_eq!(
+            line(FileType::Dockerfile, "FROM rust:1.70 AS b"),
+            "kkkk......nnnn.tt.."
+        );
+        assert_eq!(line(FileType::Dockerfile, "RUN echo a#b"), "kkk.........");
+    }
+
+    #[test]
+    fn test_richer_classes() {
+        assert_eq!(
+            line(FileType::C, "#include <stdio.h> // TODO: x"),
+            "pppppppp.sssssssss.cccxxxxccc"
+        );
+        assert_eq!(
+            line(FileType::C, "  # define MAX_LEN 10"),
+            "..pppppppp.KKKKKKK.nn"
+        );
+        assert_eq!(
+            line(
+                FileType::C,
+                "size_t n = sizeof(Foo) ? NULL : \"\\x41\\n\\101\";"
+            ),
+            "TTTTTT...o.ffffff.TTT..o.KKKK...seeeeeeeeees."
+        );
+        assert_eq!(
+            line(FileType::C, "/** doc */ /**/ /* FIXME */"),
+            "dddddddddd.CCCC.CCCxxxxxCCC"
+        );
+
+        assert_eq!(
+            line(FileType::Rust, "#![allow(dead_code)] x"),
+            "pppppppppppppppppppp.."
+        );
+        assert_eq!(
+            line(FileType::Rust, "/// docs TODOS TODO"),
+            "dddddddddddddddxxxx"
+        );
+        assert_eq!(line(FileType::Rust, "//// plain"), "cccccccccc");
+        assert_eq!(
+            line(FileType::Rust, "let v: Vec<T> = vec![Some(MAX)];"),
+            "kkk....TTToTo.o.ffff.KKKK.KKK..."
+        );
+        assert_eq!(
+            line(FileType::Rust, "let s = \"\\u{1F600}\\t\";"),
+            "kkk...o.seeeeeeeeeees."
+        );
+
+        assert_eq!(
+            line(FileType::Python, "@app.route(\"/\")"),
+            "pppppppppp.sss."
+        );
+        assert_eq!(
+            line(FileType::Python, "r = r'\\n' + '\\n'"),
+            "..o.sssss.o.sees"
+        );
+        assert_eq!(
+            line(FileType::Python, "s = 'unterminated"),
+            "..o.!ssssssssssss"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::config::state_dir;
+
+use std::fs;
+use std::path::PathBuf;
+
+const MAX_ENTRIES: usize = 100;
+
+/// Previously entered prompt input, oldest first. Histories loaded by name
+/// are kept in the state directory so they outlive the session.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+    // the entry being shown while browsing, and what was typed before.
+    browsing: Option<usize>,
+    draft: String,
+}
+
+impl History {
+    /// Reads `<state dir>/<name>`, one entry per line. A missing file just
+    /// means an empty history.
+    pub fn load(name: &str) -> History {
+        let path = state_dir().map(|dir| dir.join(name));
+        let entries = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        History {
+            entries,
+            path,
+            ..History::default()
+        }
+    }
+
+    /// Adds `entry` as the newest one, dropping an older copy of it, and
+    /// writes the history back if it has a file.
+    pub fn push(&mut self, entry: &str) -> Result<(), String> {
+        self.reset();
+        if entry.is_empty() || entry.contains('\n') {
+            return Ok(());
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(String::from(entry));
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+        self.save()
+    }
+
+    pub fn last(&self) -> Option<&str> {
+        self.entries.last().map(String::as_str)
+    }
+
+    /// Steps to an older entry. `input` is remembered on the first step so
+    /// `next` can bring it back.
+    pub fn previous(&mut self, input: &str) -> Option<String> {
+        let index = match self.browsing {
+            Some(0) => return None,
+            Some(i) => i - 1,
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = String::from(input);
+                self.entries.len() - 1
+            }
+        };
+        self.browsing = Some(index);
+        Some(self.entries[index].clone())
+    }
+
+    /// Steps to a newer entry, ending with what was typed before browsing.
+    pub fn next(&mut self) -> Option<String> {
+        let index = self.browsing?;
+        if index + 1 < self.entries.len() {
+            self.browsing = Some(index + 1);
+            Some(self.entries[index + 1].clone())
+        } else {
+            self.browsing = None;
+            Some(std::mem::take(&mut self.draft))
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.browsing = None;
+        self.draft.clear();
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let write = || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut text = self.entries.join("\n");
+            text.push('\n');
+            fs::write(path, text)
+        };
+        write().map_err(|e| format!("Can't save history to {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_push() {
+        let mut history = History::default();
+        history.push("a").unwrap();
+        history.push("b").unwrap();
+        history.push("a").unwrap();
+        history.push("").unwrap();
+        assert_eq!(history.entries, vec!["b", "a"]);
+        assert_eq!(history.last(), Some("a"));
+
+        for i in 0..MAX_ENTRIES + 5 {
+            history.push(&i.to_string()).unwrap();
+        }
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert_eq!(history.entries[0], "5");
+    }
+
+    #[test]
+    fn test_browse() {
+        let mut history = History::default();
+        history.push("one").unwrap();
+        history.push("two").unwrap();
+
+        assert_eq!(history.next(), None);
+        assert_eq!(history.previous("dra"), Some(String::from("two")));
+        assert_eq!(history.previous("two"), Some(String::from("one")));
+        assert_eq!(history.previous("one"), None);
+        assert_eq!(history.next(), Some(String::from("two")));
+        assert_eq!(history.next(), Some(String::from("dra")));
+        assert_eq!(history.next(), None);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::Row;
+
+/// How one level of indentation is written.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IndentStyle {
+    pub tabs: bool,
+    /// columns per level when indenting with spaces.
+    pub width: usize,
+}
+
+impl IndentStyle {
+    /// Indents the way most indented rows already do, with spaces when
+    /// nothing is indented yet.
+    pub fn detect(rows: &[Row], width: usize) -> Self {
+        let (mut tabs, mut spaces) = (0, 0);
+        for row in rows {
+            match row.buf.first() {
+                Some('\t') => tabs += 1,
+                Some(' ') => spaces += 1,
+                _ => {}
+            }
+        }
+        Self {
+            tabs: tabs > spaces,
+            width,
+        }
+    }
+
+    pub fn unit(&self) -> Vec<char> {
+        if self.tabs {
+            vec!['\t']
+        } else {
+            vec![' '; self.width]
+        }
+    }
+}
+
+pub fn leading_whitespace(buf: &[char]) -> &[char] {
+    let end = buf
+        .iter()
+        .position(|c| !c.is_whitespace())
+        .unwrap_or(buf.len());
+    &buf[..end]
+}
+
+/// `indent` with one level taken off the end.
+pub fn dedent(indent: &[char], style: IndentStyle) -> Vec<char> {
+    let mut indent = indent.to_vec();
+    if indent.last() == Some(&'\t') {
+        indent.pop();
+    } else {
+        for _ in 0..style.width {
+            if indent.last() != Some(&' ') {
+                break;
+            }
+            indent.pop();
+        }
+    }
+    indent
+}
+
+/// `buf` with one level of indentation added or taken off.
+pub fn shift(buf: &[char], style: IndentStyle, right: bool) -> Vec<char> {
+    let indent = leading_whitespace(buf);
+    let mut shifted = if right {
+        [indent, &style.unit()].concat()
+    } else {
+        dedent(indent, style)
+    };
+    shifted.extend_from_slice(&buf[indent.len()..]);
+    shifted
+}
+
+/// What Tab inserts at `column`: a tab, or spaces up to the next level.
+pub fn soft_tab(column: usize, style: IndentStyle) -> Vec<char> {
+    if style.tabs {
+        vec!['\t']
+    } else {
+        vec![' '; style.width - column % style.width]
+    }
+}
+
+/// How many chars Backspace deletes when `before` is what precedes the
+/// cursor: back to the previous level inside space indentation, else one.
+pub fn backspace_width(before: &[char], style: IndentStyle) -> usize {
+    if style.tabs || before.is_empty() || before.iter().any(|c| *c != ' ') {
+        return 1;
+    }
+    match before.len() % style.width {
+        0 => style.width,
+        n => n,
+    }
+}
+
+/// Splits a line at the cursor for Enter. The text after the cursor moves
+/// to a new line with the same indentation, one level deeper after an
+/// opener; a closer right after the cursor gets its own line back at the
+/// old level. Returns the lines and the cursor column on the second one.
+pub fn break_line(
+    left: &[char],
+    right: &[char],
+    openers: &[char],
+    closers: &[char],
+    style: IndentStyle,
+) -> (Vec<Vec<char>>, usize) {
+    let indent = leading_whitespace(left).to_vec();
+    let right = &right[leading_whitespace(right).len()..];
+    let opens = left
+        .iter()
+        .rev()
+        .find(|c| !c.is_whitespace())
+        .is_some_and(|c| openers.contains(c));
+    if !opens {
+        return (
+            vec![left.to_vec(), [&indent[..], right].concat()],
+            indent.len(),
+        );
+    }
+    let mut inner = indent.clone();
+    inner.extend(style.unit());
+    let column = inner.len();
+    let lines = match right.first() {
+        Some(c) if closers.contains(c) => vec![left.to_vec(), inner, [&indent[..], right].concat()],
+        _ => vec![left.to_vec(), [&inner[..], right].concat()],
+    };
+    (lines, column)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn lines(lines: &[Vec<char>]) -> Vec<String> {
+        lines.iter().map(|line| line.iter().collect()).collect()
+    }
+
+    #[test]
+    fn test_break_line() {
+        let spaces = IndentStyle {
+            tabs: false,
+            width: 4,
+        };
+        let split = |left: &str, right: &str, style| {
+            let (new_lines, column) = break_line(
+                &chars(left),
+                &chars(right),
+                &['{', '(', ':'],
+                &['}', ')'],
+                style,
+            );
+            (lines(&new_lines), column)
+        };
+
+        assert_eq!(
+            split("    let a = 1;", "", spaces),
+            (
+                vec![String::from("    let a = 1;"), String::from("    ")],
+                4
+            )
+        );
+        assert_eq!(
+            split("    foo(", "  bar)", spaces),
+            (
+                vec![String::from("    foo("), String::from("        bar)")],
+                8
+            )
+        );
+        assert_eq!(
+            split("fn main() {", "}", spaces),
+            (
+                vec![
+                    String::from("fn main() {"),
+                    String::from("    "),
+                    String::from("}")
+                ],
+                4
+            )
+        );
+        let tabs = IndentStyle {
+            tabs: true,
+            width: 4,
+        };
+        assert_eq!(
+            split("\tif x:", "", tabs),
+            (vec![String::from("\tif x:"), String::from("\t\t")], 2)
+        );
+    }
+
+    #[test]
+    fn test_dedent_and_detect() {
+        let style = IndentStyle {
+            tabs: false,
+            width: 4,
+        };
+        assert_eq!(dedent(&chars("      "), style), chars("  "));
+        assert_eq!(dedent(&chars("  "), style), chars(""));
+        assert_eq!(dedent(&chars("\t\t"), style), chars("\t"));
+
+        let rows = ["\tone", "\ttwo", "  three", "four"]
+            .iter()
+            .map(|line| Row::from(&String::from(*line)))
+            .collect::<Vec<Row>>();
+        assert!(IndentStyle::detect(&rows, 4).tabs);
+        assert!(!IndentStyle::detect(&rows[2..], 4).tabs);
+    }
+
+    #[test]
+    fn test_shift_and_soft_tabs() {
+        let spaces = IndentStyle {
+            tabs: false,
+            width: 4,
+        };
+        let tabs = IndentStyle {
+            tabs: true,
+            width: 8,
+        };
+        assert_eq!(shift(&chars("  x"), spaces, true), chars("      x"));
+        assert_eq!(shift(&chars("      x"), spaces, false), chars("  x"));
+        assert_eq!(shift(&chars("x"), spaces, false), chars("x"));
+        assert_eq!(shift(&chars("\tx"), tabs, true), chars("\t\tx"));
+
+        assert_eq!(soft_tab(0, spaces), chars("    "));
+        assert_eq!(soft_tab(6, spaces), chars("  "));
+        assert_eq!(soft_tab(6, tabs), chars("\t"));
+
+        assert_eq!(backspace_width(&chars("        "), spaces), 4);
+        assert_eq!(backspace_width(&chars("      "), spaces), 2);
+        assert_eq!(backspace_width(&chars("  x "), spaces), 1);
+        assert_eq!(backspace_width(&chars(""), spaces), 1);
+        assert_eq!(backspace_width(&chars("        "), tabs), 1);
+    }
+}
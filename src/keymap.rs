@@ -0,0 +1,400 @@
+use crate::command;
+use crate::config::{config_dir, parse_key_values};
+
+use std::collections::HashSet;
+use std::fs;
+use termion::event::Key;
+
+const KEYMAP_FILE_NAME: &str = "keymap";
+
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("C-c", "quit"),
+    ("C-q", "quit"),
+    ("Backspace", "delete-backward"),
+    ("C-h", "delete-backward"),
+    ("Delete", "delete-backward"),
+    ("C-s", "save"),
+    ("C-f", "find"),
+    ("F3", "find-next"),
+    ("F4", "find-previous"),
+    ("F5", "record-macro"),
+    ("F6", "play-macro"),
+    ("C-t", "filetype"),
+    ("C-g", "goto"),
+    ("C-p", "command-line"),
+    ("C-z", "undo"),
+    ("C-y", "redo"),
+    ("C-d", "duplicate-line"),
+    ("C-k", "delete-line"),
+    ("M-p", "move-line-up"),
+    ("M-n", "move-line-down"),
+    ("M-j", "join-lines"),
+    ("M-u", "add-cursor-above"),
+    ("M-d", "add-cursor-below"),
+    ("M-o", "add-next-occurrence"),
+    ("M-r", "rectangle-mark"),
+    ("M-c", "copy-rectangle"),
+    ("M-x", "kill-rectangle"),
+    ("M-v", "yank-rectangle"),
+    ("M-y", "paste-history"),
+    ("Esc", "cancel"),
+    ("C-]", "match-bracket"),
+    ("C-/", "toggle-comment"),
+    ("Tab", "indent"),
+    ("BackTab", "dedent"),
+    ("Left", "move-left"),
+    ("Right", "move-right"),
+    ("Up", "move-up"),
+    ("Down", "move-down"),
+];
+
+/// Bindings of the `editing = emacs` preset, used instead of the defaults.
+const EMACS_BINDINGS: &[(&str, &str)] = &[
+    ("C-x C-c", "quit"),
+    ("C-x C-s", "save"),
+    ("Backspace", "delete-backward"),
+    ("Delete", "delete-backward"),
+    ("C-s", "find"),
+    ("C-r", "find-previous"),
+    ("F3", "find-next"),
+    ("F4", "find-previous"),
+    ("M-g", "goto"),
+    ("M-x", "command-line"),
+    ("C-x (", "record-macro"),
+    ("C-x )", "record-macro"),
+    ("C-x e", "play-macro"),
+    ("C-]", "match-bracket"),
+    ("M-;", "toggle-comment"),
+    ("C-/", "undo"),
+    ("C-x u", "undo"),
+    ("M-_", "redo"),
+    ("M-p", "move-line-up"),
+    ("M-n", "move-line-down"),
+    ("M-j", "join-lines"),
+    ("C-a", "line-start"),
+    ("C-e", "line-end"),
+    ("C-f", "move-right"),
+    ("C-b", "move-left"),
+    ("C-n", "move-down"),
+    ("C-p", "move-up"),
+    ("Left", "move-left"),
+    ("Right", "move-right"),
+    ("Up", "move-up"),
+    ("Down", "move-down"),
+    ("Tab", "indent"),
+    ("BackTab", "dedent"),
+    ("C-k", "kill-line"),
+    ("C-w", "kill-region"),
+    ("M-w", "copy-region"),
+    ("C-y", "yank"),
+    ("M-y", "yank-pop"),
+    ("C-Space", "set-mark"),
+    ("C-x Space", "rectangle-mark"),
+    ("C-x r M-w", "copy-rectangle"),
+    ("C-x r k", "kill-rectangle"),
+    ("C-x r y", "yank-rectangle"),
+    ("M-|", "filter"),
+    ("C-x r t", "string-rectangle"),
+    ("C-x r s", "copy-to-register"),
+    ("C-x r i", "insert-register"),
+    ("C-g", "cancel"),
+];
+
+/// What a sequence of keys means so far.
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    /// the command line to run.
+    Command(String),
+    /// the start of a longer binding; wait for more keys.
+    Prefix,
+    None,
+}
+
+/// Maps key sequences to command lines.
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, String)>,
+    // sequences bound by the user rather than by default.
+    user_bound: HashSet<Vec<Key>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::with_bindings(DEFAULT_BINDINGS)
+    }
+}
+
+impl Keymap {
+    fn with_bindings(bindings: &[(&str, &str)]) -> Self {
+        let bindings = bindings
+            .iter()
+            .map(|(keys, command)| (parse_keys(keys).unwrap(), String::from(*command)))
+            .collect();
+        Self {
+            bindings,
+            user_bound: HashSet::new(),
+        }
+    }
+
+    /// The bindings for an editing mode.
+    pub fn preset(editing_mode: &str) -> Self {
+        match editing_mode {
+            "emacs" => Self::with_bindings(EMACS_BINDINGS),
+            _ => Self::default(),
+        }
+    }
+
+    /// The bindings for `editing_mode` with `<config dir>/keymap` applied on
+    /// top. Problems with the file are reported and the offending lines
+    /// skipped.
+    pub fn load(editing_mode: &str) -> (Keymap, Vec<String>) {
+        let mut keymap = Keymap::preset(editing_mode);
+        let text = config_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(KEYMAP_FILE_NAME)).ok())
+            .unwrap_or_default();
+        let errors = keymap.apply(&text);
+        (keymap, errors)
+    }
+
+    /// Applies `keys = command` lines; `keys = none` removes a binding.
+    pub fn apply(&mut self, text: &str) -> Vec<String> {
+        let entries = match parse_key_values(text) {
+            Ok(entries) => entries,
+            Err(e) => return vec![format!("keymap: {}", e)],
+        };
+        let mut errors = vec![];
+        for (line, keys, command_line) in entries {
+            let result = parse_keys(&keys).and_then(|keys| {
+                if command_line == "none" {
+                    self.unbind(&keys);
+                    return Ok(());
+                }
+                command::parse(&command_line)?;
+                self.bind(keys, command_line)
+            });
+            if let Err(e) = result {
+                errors.push(format!("keymap line {}: {}", line, e));
+            }
+        }
+        errors
+    }
+
+    /// Binds `keys`, replacing a default binding of the same sequence. A
+    /// sequence can't be bound twice, or be a prefix of another binding.
+    pub fn bind(&mut self, keys: Vec<Key>, command_line: String) -> Result<(), String> {
+        if self.user_bound.contains(&keys) {
+            return Err(format!("{} is bound twice", keys_to_string(&keys)));
+        }
+        self.unbind(&keys);
+        let conflict = self
+            .bindings
+            .iter()
+            .find(|(bound, _)| bound.starts_with(&keys) || keys.starts_with(bound));
+        if let Some((bound, command)) = conflict {
+            return Err(format!(
+                "{} conflicts with {} = {}",
+                keys_to_string(&keys),
+                keys_to_string(bound),
+                command
+            ));
+        }
+        self.user_bound.insert(keys.clone());
+        self.bindings.push((keys, command_line));
+        Ok(())
+    }
+
+    fn unbind(&mut self, keys: &[Key]) {
+        self.bindings.retain(|(bound, _)| bound.as_slice() != keys);
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        let mut lookup = Lookup::None;
+        for (bound, command) in &self.bindings {
+            if bound.as_slice() == keys {
+                return Lookup::Command(command.clone());
+            }
+            if bound.starts_with(keys) {
+                lookup = Lookup::Prefix;
+            }
+        }
+        lookup
+    }
+}
+
+/// Parses a space separated key sequence such as `C-x C-s`.
+pub fn parse_keys(s: &str) -> Result<Vec<Key>, String> {
+    let keys = s
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<Vec<Key>, String>>()?;
+    if keys.is_empty() {
+        return Err(String::from("no keys given"));
+    }
+    Ok(keys)
+}
+
+/// `C-x`/`Ctrl-x`, `M-x`/`Alt-x`, `F1`..`F12`, names like `Left` or
+/// `Backspace`, or a single character.
+pub fn parse_key(s: &str) -> Result<Key, String> {
+    let single = |rest: &str| {
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let modified = |prefixes: &[&str]| {
+        prefixes
+            .iter()
+            .find_map(|prefix| s.strip_prefix(prefix))
+            .and_then(|rest| match rest {
+                "Space" | "space" => Some(' '),
+                _ => single(rest),
+            })
+    };
+    if let Some(c) = modified(&["C-", "Ctrl-"]) {
+        // terminals send C-Space as a NUL byte, and termion reports C-\ to
+        // C-_ as C-4 to C-7. C-/ sends the same byte as C-_.
+        return Ok(match c {
+            ' ' | '@' => Key::Null,
+            '/' => Key::Ctrl('7'),
+            '\\' | ']' | '^' | '_' => Key::Ctrl((b'4' + (c as u8 - b'\\')) as char),
+            _ => Key::Ctrl(c.to_ascii_lowercase()),
+        });
+    }
+    if let Some(c) = modified(&["M-", "Alt-"]) {
+        return Ok(Key::Alt(c));
+    }
+    if let Some(n) = s.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=12).contains(&n) {
+            return Ok(Key::F(n));
+        }
+    }
+    let key = match s.to_lowercase().as_str() {
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "insert" => Key::Insert,
+        "esc" | "escape" => Key::Esc,
+        "backtab" => Key::BackTab,
+        "enter" | "return" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "space" => Key::Char(' '),
+        _ => match single(s) {
+            Some(c) => Key::Char(c),
+            None => return Err(format!("unknown key `{}`", s)),
+        },
+    };
+    Ok(key)
+}
+
+/// The inverse of `parse_key`, for messages.
+pub fn key_to_string(key: &Key) -> String {
+    match key {
+        Key::Null => String::from("C-Space"),
+        Key::Ctrl(c @ '4'..='7') => format!("C-{}", (b'\\' + (*c as u8 - b'4')) as char),
+        Key::Ctrl(c) => format!("C-{}", c),
+        Key::Alt(c) => format!("M-{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Char('\n') => String::from("Enter"),
+        Key::Char('\t') => String::from("Tab"),
+        Key::Char(' ') => String::from("Space"),
+        Key::Char(c) => c.to_string(),
+        Key::BackTab => String::from("BackTab"),
+        Key::PageUp => String::from("PageUp"),
+        Key::PageDown => String::from("PageDown"),
+        other => format!("{:?}", other),
+    }
+}
+
+pub fn keys_to_string(keys: &[Key]) -> String {
+    keys.iter()
+        .map(key_to_string)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(
+            parse_keys("C-x Ctrl-S"),
+            Ok(vec![Key::Ctrl('x'), Key::Ctrl('s')])
+        );
+        assert_eq!(
+            parse_keys("M-y F3 Left"),
+            Ok(vec![Key::Alt('y'), Key::F(3), Key::Left])
+        );
+        assert_eq!(parse_key("C-Space"), Ok(Key::Null));
+        assert_eq!(key_to_string(&Key::Null), "C-Space");
+        assert_eq!(parse_key("C-]"), Ok(Key::Ctrl('5')));
+        assert_eq!(key_to_string(&Key::Ctrl('5')), "C-]");
+        assert_eq!(parse_key("C-/"), parse_key("C-_"));
+        assert_eq!(parse_key("Enter"), Ok(Key::Char('\n')));
+        assert_eq!(parse_key(":"), Ok(Key::Char(':')));
+        assert!(parse_key("C-xy").is_err());
+        assert!(parse_key("F13").is_err());
+        assert_eq!(keys_to_string(&parse_keys("C-x  C-s").unwrap()), "C-x C-s");
+    }
+
+    #[test]
+    fn test_lookup_and_conflicts() {
+        let mut keymap = Keymap::default();
+        let errors = keymap.apply(
+            "C-h = none\nC-x C-s = save\nC-x C-c = quit\nC-q = goto 1\nC-x = find\n\
+             C-x C-s = quit\nC-y = frobnicate\nC-z = replace a\nC-x C-f = filetype rust\n",
+        );
+        assert_eq!(errors.len(), 4);
+        assert!(errors[0].starts_with("keymap line 5: C-x conflicts with C-x C-s"));
+        assert_eq!(errors[1], "keymap line 6: C-x C-s is bound twice");
+        assert_eq!(errors[2], "keymap line 7: Unknown command: frobnicate");
+        assert_eq!(errors[3], "keymap line 8: Usage: replace <from> <to>");
+
+        assert_eq!(keymap.lookup(&[Key::Ctrl('h')]), Lookup::None);
+        assert_eq!(keymap.lookup(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Ctrl('s')]),
+            Lookup::Command(String::from("save"))
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('q')]),
+            Lookup::Command(String::from("goto 1"))
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Ctrl('f')]),
+            Lookup::Command(String::from("filetype rust"))
+        );
+        assert_eq!(keymap.lookup(&[Key::Char('a')]), Lookup::None);
+    }
+
+    #[test]
+    fn test_emacs_preset() {
+        let keymap = Keymap::preset("emacs");
+        assert_eq!(keymap.lookup(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Ctrl('s')]),
+            Lookup::Command(String::from("save"))
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Alt('y')]),
+            Lookup::Command(String::from("yank-pop"))
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Null]),
+            Lookup::Command(String::from("set-mark"))
+        );
+        for (_, command_line) in &keymap.bindings {
+            assert!(command::parse(command_line).is_ok(), "{}", command_line);
+        }
+        assert_eq!(Keymap::preset("vim").bindings, Keymap::default().bindings);
+    }
+}
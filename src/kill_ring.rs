@@ -0,0 +1,93 @@
+use crate::registers::Register;
+
+const MAX_KILLS: usize = 60;
+
+/// Killed and yanked text, newest last, for yanking back. This is also the
+/// clipboard history.
+#[derive(Default)]
+pub struct KillRing {
+    kills: Vec<Register>,
+    // how far back from the newest kill the last yank reached.
+    yank_offset: usize,
+}
+
+impl KillRing {
+    /// Records killed text. With `append` it is joined onto the newest
+    /// kill, so a run of kills yanks back as one piece.
+    pub fn kill(&mut self, text: &str, append: bool) {
+        match self.kills.last_mut() {
+            Some(last) if append => {
+                self.yank_offset = 0;
+                last.text.push_str(text);
+            }
+            _ => self.push(Register {
+                text: String::from(text),
+                linewise: false,
+            }),
+        }
+    }
+
+    /// Records `register` as the newest kill.
+    pub fn push(&mut self, register: Register) {
+        self.yank_offset = 0;
+        if register.text.is_empty() && !register.linewise {
+            return;
+        }
+        self.kills.push(register);
+        if self.kills.len() > MAX_KILLS {
+            self.kills.remove(0);
+        }
+    }
+
+    /// The kill `n` back from the newest one.
+    pub fn get(&self, n: usize) -> Option<&Register> {
+        self.kills.iter().rev().nth(n)
+    }
+
+    /// Every kill, newest first.
+    pub fn recent(&self) -> impl Iterator<Item = &Register> {
+        self.kills.iter().rev()
+    }
+
+    /// The newest kill.
+    pub fn yank(&mut self) -> Option<&Register> {
+        self.yank_offset = 0;
+        self.kills.last()
+    }
+
+    /// The kill before the one yanked last, wrapping around to the newest.
+    pub fn yank_pop(&mut self) -> Option<&Register> {
+        if self.kills.is_empty() {
+            return None;
+        }
+        self.yank_offset = (self.yank_offset + 1) % self.kills.len();
+        let index = self.kills.len() - 1 - self.yank_offset;
+        Some(&self.kills[index])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kill_and_yank() {
+        let mut ring = KillRing::default();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.yank_pop(), None);
+
+        let text = |register: Option<&Register>| register.map(|r| r.text.clone());
+        ring.kill("one", false);
+        ring.kill("two", false);
+        ring.kill("\n", true);
+        ring.kill("", false);
+        assert_eq!(text(ring.yank()), Some(String::from("two\n")));
+        assert_eq!(text(ring.yank_pop()), Some(String::from("one")));
+        assert_eq!(text(ring.yank_pop()), Some(String::from("two\n")));
+
+        ring.kill("three", false);
+        assert_eq!(text(ring.yank_pop()), Some(String::from("two\n")));
+        assert_eq!(text(ring.yank()), Some(String::from("three")));
+        assert_eq!(text(ring.get(2)), Some(String::from("one")));
+    }
+}
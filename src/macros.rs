@@ -0,0 +1,152 @@
+use crate::config::{config_dir, parse_key_values};
+use crate::keymap::{keys_to_string, parse_keys};
+
+use std::collections::BTreeMap;
+use std::fs;
+use termion::event::Key;
+
+const MACROS_FILE_NAME: &str = "macros";
+
+/// The register macros are recorded into and played from when none is
+/// named.
+pub const DEFAULT_REGISTER: char = 'q';
+
+/// Recorded key sequences by register, and the one being recorded.
+#[derive(Default)]
+pub struct Macros {
+    registers: BTreeMap<char, Vec<Key>>,
+    recording: Option<(char, Vec<Key>)>,
+    // how long the recording was when the current key sequence began, so
+    // the keys that stop the recording can be left out of it.
+    command_start: usize,
+    /// the register last recorded or played.
+    pub last: Option<char>,
+}
+
+impl Macros {
+    /// Reads the macros saved in the config directory, one `register =
+    /// keys` line each.
+    pub fn load() -> (Macros, Vec<String>) {
+        let mut macros = Macros::default();
+        let text = config_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(MACROS_FILE_NAME)).ok())
+            .unwrap_or_default();
+        let errors = macros.apply(&text);
+        (macros, errors)
+    }
+
+    fn apply(&mut self, text: &str) -> Vec<String> {
+        let entries = match parse_key_values(text) {
+            Ok(entries) => entries,
+            Err(e) => return vec![format!("macros: {}", e)],
+        };
+        let mut errors = vec![];
+        for (line, register, keys) in entries {
+            let register = match register_name(&register) {
+                Ok(register) => register,
+                Err(e) => {
+                    errors.push(format!("macros line {}: {}", line, e));
+                    continue;
+                }
+            };
+            match parse_keys(&keys) {
+                Ok(keys) => {
+                    self.registers.insert(register, keys);
+                }
+                Err(e) => errors.push(format!("macros line {}: {}", line, e)),
+            }
+        }
+        errors
+    }
+
+    /// Writes every macro to the config directory.
+    pub fn save(&self) -> Result<(), String> {
+        let dir = config_dir().ok_or("no config directory")?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        fs::write(dir.join(MACROS_FILE_NAME), self.to_text()).map_err(|e| e.to_string())
+    }
+
+    fn to_text(&self) -> String {
+        self.registers
+            .iter()
+            .map(|(register, keys)| format!("{} = {}\n", register, keys_to_string(keys)))
+            .collect()
+    }
+
+    pub fn get(&self, register: char) -> Option<&[Key]> {
+        self.registers.get(&register).map(Vec::as_slice)
+    }
+
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    pub fn start(&mut self, register: char) {
+        self.recording = Some((register, vec![]));
+        self.command_start = 0;
+    }
+
+    /// Marks where a new key sequence begins.
+    pub fn begin_command(&mut self) {
+        if let Some((_, keys)) = &self.recording {
+            self.command_start = keys.len();
+        }
+    }
+
+    pub fn record(&mut self, key: Key) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// Ends the recording, leaving out the key sequence that ended it, and
+    /// returns the register and how many keys went into it.
+    pub fn stop(&mut self) -> Option<(char, usize)> {
+        let (register, mut keys) = self.recording.take()?;
+        keys.truncate(self.command_start);
+        let len = keys.len();
+        self.registers.insert(register, keys);
+        self.last = Some(register);
+        Some((register, len))
+    }
+}
+
+/// A register named by one letter or digit.
+pub fn register_name(name: &str) -> Result<char, String> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_alphanumeric() => Ok(c),
+        _ => Err(format!("`{}` is not a register", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_and_save() {
+        let mut macros = Macros::default();
+        macros.start('a');
+        macros.record(Key::Char('x'));
+        macros.begin_command();
+        macros.record(Key::Alt('m'));
+        macros.record(Key::Ctrl('5'));
+        macros.begin_command();
+        macros.record(Key::F(5));
+        assert_eq!(macros.stop(), Some(('a', 3)));
+        assert_eq!(
+            macros.get('a'),
+            Some(&[Key::Char('x'), Key::Alt('m'), Key::Ctrl('5')][..])
+        );
+
+        let mut loaded = Macros::default();
+        let errors = loaded.apply(&(macros.to_text() + "ab = x\nb = F99\nc = Space Enter\n"));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(loaded.get('a'), macros.get('a'));
+        assert_eq!(
+            loaded.get('c'),
+            Some(&[Key::Char(' '), Key::Char('\n')][..])
+        );
+    }
+}
@@ -2,11 +2,35 @@
 
 use editor::Editor;
 
+mod brackets;
+mod command;
+mod comment;
+mod config;
+mod cursors;
 mod document;
 mod editor;
+mod filter;
+mod format;
+mod highlighter;
 mod highlight;
+mod history;
+mod indent;
+mod keymap;
+mod kill_ring;
+mod macros;
+mod pairs;
+mod prompt;
+mod rectangle;
+mod registers;
 mod row;
+mod syntax;
 mod terminal;
+mod theme;
+mod transform;
+#[cfg(feature = "tree-sitter-highlight")]
+mod treesitter;
+mod undo;
+mod vim;
 
 pub use document::Document;
 pub use highlight::Highlight;
@@ -0,0 +1,77 @@
+/// `c` as the opener of one of `pairs`, with its closer.
+pub fn closer(pairs: &[(char, char)], c: char) -> Option<char> {
+    pairs
+        .iter()
+        .find(|(open, _)| *open == c)
+        .map(|(_, close)| *close)
+}
+
+/// The closer to insert along with `c`, if `c` opens a pair and the text
+/// around the cursor suggests a new pair is wanted: brackets only before
+/// blanks or closers, and quotes not inside a word.
+pub fn pair_for(
+    pairs: &[(char, char)],
+    c: char,
+    before: Option<char>,
+    after: Option<char>,
+) -> Option<char> {
+    let close = closer(pairs, c)?;
+    let free_after = match after {
+        None => true,
+        Some(a) => a.is_whitespace() || pairs.iter().any(|(_, close)| *close == a && a != c),
+    };
+    let in_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    if close == c && (in_word(before) || in_word(after)) {
+        return None;
+    }
+    if free_after {
+        Some(close)
+    } else {
+        None
+    }
+}
+
+/// Whether typing `c` should step over the same closer after the cursor.
+pub fn types_over(pairs: &[(char, char)], c: char, after: Option<char>) -> bool {
+    after == Some(c) && pairs.iter().any(|(_, close)| *close == c)
+}
+
+/// Whether the cursor sits between the two halves of an empty pair.
+pub fn in_empty_pair(pairs: &[(char, char)], before: Option<char>, after: Option<char>) -> bool {
+    match (before, after) {
+        (Some(b), Some(a)) => closer(pairs, b) == Some(a),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('"', '"')];
+
+    #[test]
+    fn test_pair_for() {
+        assert_eq!(pair_for(PAIRS, '(', None, None), Some(')'));
+        assert_eq!(pair_for(PAIRS, '(', Some('f'), Some(' ')), Some(')'));
+        assert_eq!(pair_for(PAIRS, '[', Some('('), Some(')')), Some(']'));
+        assert_eq!(pair_for(PAIRS, '(', None, Some('x')), None);
+        assert_eq!(pair_for(PAIRS, '"', Some(' '), None), Some('"'));
+        assert_eq!(pair_for(PAIRS, '"', Some('t'), None), None);
+        assert_eq!(pair_for(PAIRS, '"', None, Some('"')), None);
+        assert_eq!(pair_for(PAIRS, '{', None, None), None);
+    }
+
+    #[test]
+    fn test_type_over_and_delete() {
+        assert!(types_over(PAIRS, ')', Some(')')));
+        assert!(types_over(PAIRS, '"', Some('"')));
+        assert!(!types_over(PAIRS, ')', Some(']')));
+        assert!(!types_over(PAIRS, '(', Some('(')));
+
+        assert!(in_empty_pair(PAIRS, Some('('), Some(')')));
+        assert!(in_empty_pair(PAIRS, Some('"'), Some('"')));
+        assert!(!in_empty_pair(PAIRS, Some('('), Some(']')));
+        assert!(!in_empty_pair(PAIRS, None, Some(')')));
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::Path;
+use termion::event::Key;
+
+/// Lists the candidates that could replace the whole input on Tab.
+pub type Completer = fn(&str) -> Vec<String>;
+
+/// A single line of editable input with a cursor and readline-style keys,
+/// shared by every prompt.
+#[derive(Default)]
+pub struct LineInput {
+    text: Vec<char>,
+    cursor: usize,
+    completer: Option<Completer>,
+    // candidates being cycled through by repeated Tabs.
+    completions: Vec<String>,
+    completion_index: usize,
+}
+
+impl LineInput {
+    pub fn new(completer: Option<Completer>) -> Self {
+        Self {
+            completer,
+            ..Self::default()
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replaces the input, leaving the cursor at the end.
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+    }
+
+    pub fn handle_key(&mut self, key: &Key) {
+        if *key != Key::Char('\t') {
+            self.completions.clear();
+        }
+        match key {
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.text.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.text.len(),
+            Key::Backspace | Key::Ctrl('h') if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            Key::Delete | Key::Ctrl('d') if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            Key::Ctrl('w') => {
+                let mut start = self.cursor;
+                while start > 0 && self.text[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !self.text[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Ctrl('u') => {
+                self.text.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('k') => self.text.truncate(self.cursor),
+            Key::Char('\t') => self.complete(),
+            Key::Char(c) if !c.is_control() => {
+                self.text.insert(self.cursor, *c);
+                self.cursor += 1;
+            }
+            _ => {}
+        }
+    }
+
+    // the first Tab fills in as much as all candidates share; once that
+    // stops helping, each Tab moves on to the next candidate.
+    fn complete(&mut self) {
+        if !self.completions.is_empty() {
+            self.completion_index = (self.completion_index + 1) % self.completions.len();
+            let candidate = self.completions[self.completion_index].clone();
+            self.set_text(&candidate);
+            return;
+        }
+        let completer = match self.completer {
+            Some(completer) => completer,
+            None => return,
+        };
+        let text = self.text();
+        let candidates = completer(&text);
+        let prefix = common_prefix(&candidates);
+        if candidates.len() == 1 || prefix.chars().count() > self.text.len() {
+            self.set_text(&prefix);
+        } else if candidates.len() > 1 {
+            self.set_text(&candidates[0]);
+            self.completions = candidates;
+            self.completion_index = 0;
+        }
+    }
+}
+
+fn common_prefix(candidates: &[String]) -> String {
+    let first = match candidates.first() {
+        Some(first) => first,
+        None => return String::new(),
+    };
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .map(|((i, a), _)| i + a.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(len);
+    }
+    String::from(&first[..len])
+}
+
+/// Completes the last component of a path, adding `/` to directories.
+pub fn complete_path(input: &str) -> Vec<String> {
+    let (dir, file_prefix) = match input.rfind('/') {
+        Some(at) => (&input[..=at], &input[at + 1..]),
+        None => ("", input),
+    };
+    let read_dir = if dir.is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(Path::new(dir))
+    };
+    let entries = match read_dir {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut candidates = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(file_prefix) || (name.starts_with('.') && file_prefix.is_empty()) {
+                return None;
+            }
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect::<Vec<String>>();
+    candidates.sort();
+    candidates
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn typed(text: &str) -> LineInput {
+        let mut input = LineInput::default();
+        for c in text.chars() {
+            input.handle_key(&Key::Char(c));
+        }
+        input
+    }
+
+    #[test]
+    fn test_editing_keys() {
+        let mut input = typed("hello world");
+        input.handle_key(&Key::Ctrl('a'));
+        input.handle_key(&Key::Right);
+        input.handle_key(&Key::Char('X'));
+        assert_eq!(input.text(), "hXello world");
+        assert_eq!(input.cursor(), 2);
+
+        input.handle_key(&Key::Ctrl('k'));
+        assert_eq!(input.text(), "hX");
+        input.handle_key(&Key::Backspace);
+        assert_eq!(input.text(), "h");
+
+        let mut input = typed("cargo build  --release");
+        input.handle_key(&Key::Ctrl('w'));
+        assert_eq!(input.text(), "cargo build  ");
+        input.handle_key(&Key::Ctrl('w'));
+        assert_eq!(input.text(), "cargo ");
+        input.handle_key(&Key::Left);
+        input.handle_key(&Key::Ctrl('u'));
+        assert_eq!(input.text(), " ");
+        assert_eq!(input.cursor(), 0);
+        input.handle_key(&Key::Delete);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_completion() {
+        fn colours(input: &str) -> Vec<String> {
+            ["green", "grey", "red"]
+                .iter()
+                .filter(|c| c.starts_with(input))
+                .map(|c| String::from(*c))
+                .collect()
+        }
+        let mut input = LineInput::new(Some(colours));
+        input.handle_key(&Key::Char('r'));
+        input.handle_key(&Key::Char('\t'));
+        assert_eq!(input.text(), "red");
+
+        input.set_text("g");
+        input.handle_key(&Key::Char('\t'));
+        assert_eq!(input.text(), "gre");
+        input.handle_key(&Key::Char('\t'));
+        assert_eq!(input.text(), "green");
+        input.handle_key(&Key::Char('\t'));
+        assert_eq!(input.text(), "grey");
+        input.handle_key(&Key::Char('\t'));
+        assert_eq!(input.text(), "green");
+    }
+
+    #[test]
+    fn test_complete_path() {
+        assert_eq!(complete_path("src/promp"), vec!["src/prompt.rs"]);
+        assert!(complete_path("sr").contains(&String::from("src/")));
+    }
+}
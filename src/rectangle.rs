@@ -0,0 +1,95 @@
+use std::ops::Range;
+
+// the render width of `c`, as `Editor::editor_update_row` expands it.
+fn width(c: char, tab_stop: usize) -> usize {
+    if c == '\t' {
+        tab_stop
+    } else {
+        1
+    }
+}
+
+fn render_width(buf: &[char], tab_stop: usize) -> usize {
+    buf.iter().map(|c| width(*c, tab_stop)).sum()
+}
+
+// the index of the first char of `buf` starting at or after `column`.
+fn index_at(buf: &[char], column: usize, tab_stop: usize) -> usize {
+    let mut render = 0;
+    for (i, c) in buf.iter().enumerate() {
+        if render >= column {
+            return i;
+        }
+        render += width(*c, tab_stop);
+    }
+    buf.len()
+}
+
+/// The chars of `buf` that start in render columns `left..right`.
+pub fn column_range(buf: &[char], left: usize, right: usize, tab_stop: usize) -> Range<usize> {
+    let start = index_at(buf, left, tab_stop);
+    start..index_at(buf, right, tab_stop).max(start)
+}
+
+/// The text of `buf` in render columns `left..right`, padded with spaces
+/// to the full width when the row ends before `right`.
+pub fn extract(buf: &[char], left: usize, right: usize, tab_stop: usize) -> Vec<char> {
+    let mut text = buf[column_range(buf, left, right, tab_stop)].to_vec();
+    let end = render_width(buf, tab_stop).max(left);
+    text.extend(vec![' '; right.saturating_sub(end)]);
+    text
+}
+
+/// `buf` with `text` put in at render column `column`, padded with spaces
+/// up to it when the row is shorter, and the index just after `text`.
+pub fn insert_at_column(
+    buf: &[char],
+    column: usize,
+    text: &[char],
+    tab_stop: usize,
+) -> (Vec<char>, usize) {
+    let mut buf = buf.to_vec();
+    let short = column.saturating_sub(render_width(&buf, tab_stop));
+    buf.extend(vec![' '; short]);
+    let at = index_at(&buf, column, tab_stop);
+    buf.splice(at..at, text.iter().copied());
+    (buf, at + text.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn test_extract() {
+        let row = chars("ab\tcd");
+        assert_eq!(column_range(&row, 1, 3, 4), 1..3);
+        // the tab covers columns 2 to 5.
+        assert_eq!(extract(&row, 2, 6, 4), chars("\t"));
+        assert_eq!(extract(&row, 3, 7, 4), chars("c"));
+        assert_eq!(extract(&row, 7, 9, 4), chars("d "));
+        assert_eq!(extract(&chars("a"), 3, 5, 4), chars("  "));
+        assert_eq!(extract(&row, 1, 1, 4), chars(""));
+    }
+
+    #[test]
+    fn test_insert_at_column() {
+        let text = chars("xy");
+        assert_eq!(
+            insert_at_column(&chars("abc"), 1, &text, 4),
+            (chars("axybc"), 3)
+        );
+        assert_eq!(
+            insert_at_column(&chars("\tb"), 4, &text, 4),
+            (chars("\txyb"), 3)
+        );
+        assert_eq!(
+            insert_at_column(&chars("a"), 3, &text, 4),
+            (chars("a  xy"), 5)
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use crate::config::state_dir;
+use crate::kill_ring::KillRing;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+const REGISTERS_FILE_NAME: &str = "registers";
+
+/// Text yanked or deleted, and whether it is whole lines.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Register {
+    pub text: String,
+    pub linewise: bool,
+}
+
+/// The clipboard history and the named registers `a` to `z`, kept in the
+/// state directory so they outlive the session.
+#[derive(Default)]
+pub struct Registers {
+    /// every yank and delete, newest last.
+    pub ring: KillRing,
+    named: BTreeMap<char, Register>,
+    path: Option<PathBuf>,
+}
+
+impl Registers {
+    /// Reads `<state dir>/registers`. A missing or garbled file just means
+    /// empty registers.
+    pub fn load() -> Registers {
+        let path = state_dir().map(|dir| dir.join(REGISTERS_FILE_NAME));
+        let mut registers = Registers::default();
+        if let Some(text) = path.as_ref().and_then(|path| fs::read_to_string(path).ok()) {
+            registers.apply(&text);
+        }
+        registers.path = path;
+        registers
+    }
+
+    // one `<name> <c|l> <text>` line per register, with the history as
+    // `history` lines, oldest first.
+    fn apply(&mut self, text: &str) {
+        for line in text.lines() {
+            let mut fields = line.splitn(3, ' ');
+            let (name, kind, text) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(kind @ "c"), Some(text))
+                | (Some(name), Some(kind @ "l"), Some(text)) => (name, kind, text),
+                _ => continue,
+            };
+            let register = Register {
+                text: unescape(text),
+                linewise: kind == "l",
+            };
+            let mut chars = name.chars();
+            if name == "history" {
+                self.ring.push(register);
+            } else if let (Some(c), None) = (chars.next(), chars.next()) {
+                if c.is_ascii_lowercase() {
+                    self.named.insert(c, register);
+                }
+            }
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut history = self.ring.recent().collect::<Vec<&Register>>();
+        history.reverse();
+        let line = |name: &str, register: &Register| {
+            let kind = if register.linewise { "l" } else { "c" };
+            format!("{} {} {}\n", name, kind, escape(&register.text))
+        };
+        let mut text = history
+            .iter()
+            .map(|register| line("history", register))
+            .collect::<String>();
+        for (name, register) in &self.named {
+            text.push_str(&line(&name.to_string(), register));
+        }
+        text
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let write = || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, self.to_text())
+        };
+        write().map_err(|e| format!("Can't save registers to {}: {}", path.display(), e))
+    }
+
+    /// Records yanked or deleted text in the history, and in register
+    /// `name` too when one is given. An uppercase name appends to the
+    /// lowercase register.
+    pub fn store(&mut self, name: Option<char>, register: Register) -> Result<(), String> {
+        if let Some(name) = name.filter(|c| c.is_ascii_alphabetic()) {
+            let named = self.named.entry(name.to_ascii_lowercase()).or_default();
+            if name.is_ascii_uppercase() && !named.text.is_empty() {
+                if named.linewise || register.linewise {
+                    named.text.push('\n');
+                }
+                named.text.push_str(&register.text);
+                named.linewise |= register.linewise;
+            } else {
+                *named = register.clone();
+            }
+        }
+        self.ring.push(register);
+        self.save()
+    }
+
+    /// Records killed text in the history, joined onto the newest entry
+    /// with `append`.
+    pub fn kill(&mut self, text: &str, append: bool) -> Result<(), String> {
+        self.ring.kill(text, append);
+        self.save()
+    }
+
+    /// A named register, or for a digit that many entries back in the
+    /// history; `"` is the newest entry.
+    pub fn get(&self, name: char) -> Option<&Register> {
+        match name {
+            '"' => self.ring.get(0),
+            '0'..='9' => self.ring.get(name as usize - '0' as usize),
+            _ => self.named.get(&name.to_ascii_lowercase()),
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                unescaped.push('\\');
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn register(text: &str, linewise: bool) -> Register {
+        Register {
+            text: String::from(text),
+            linewise,
+        }
+    }
+
+    #[test]
+    fn test_store_and_get() {
+        let mut registers = Registers::default();
+        registers.store(None, register("one", false)).unwrap();
+        registers.store(Some('a'), register("two", true)).unwrap();
+        registers
+            .store(Some('A'), register("three", false))
+            .unwrap();
+        registers.kill("four", false).unwrap();
+
+        assert_eq!(registers.get('a'), Some(&register("two\nthree", true)));
+        assert_eq!(registers.get('"'), Some(&register("four", false)));
+        assert_eq!(registers.get('1'), Some(&register("three", false)));
+        assert_eq!(registers.get('3'), Some(&register("one", false)));
+        assert_eq!(registers.get('b'), None);
+    }
+
+    #[test]
+    fn test_save_format() {
+        let mut registers = Registers::default();
+        registers.store(None, register("a\\n\nb", false)).unwrap();
+        registers.store(Some('x'), register("line", true)).unwrap();
+        let text = registers.to_text();
+        assert_eq!(text, "history c a\\\\n\\nb\nhistory l line\nx l line\n");
+
+        let mut loaded = Registers::default();
+        loaded.apply(&(text + "garbage\n"));
+        assert_eq!(loaded.get('1'), registers.get('1'));
+        assert_eq!(loaded.get('x'), registers.get('x'));
+        assert_eq!(loaded.to_text(), registers.to_text());
+    }
+}
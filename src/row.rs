@@ -24,15 +24,45 @@ impl Row {
         self.render[start..end].iter().collect()
     }
 
+    pub fn from_buf(buf: Vec<char>) -> Self {
+        Self {
+            buf,
+            render: vec![],
+            highlight: vec![],
+        }
+    }
+
     pub fn buf_len(&self) -> usize {
         self.buf.len()
     }
+
+    /// Where the character at `buf_index` lands once tabs are expanded.
+    pub fn render_index(&self, buf_index: usize, tab_stop: usize) -> usize {
+        self.buf
+            .iter()
+            .take(buf_index)
+            .map(|c| if *c == '\t' { tab_stop } else { 1 })
+            .sum()
+    }
+
+    /// The character that render column `render_index` belongs to; the
+    /// inverse of `render_index`.
+    pub fn buf_index(&self, render_index: usize, tab_stop: usize) -> usize {
+        let mut column = 0;
+        for (i, c) in self.buf.iter().enumerate() {
+            column += if *c == '\t' { tab_stop } else { 1 };
+            if column > render_index {
+                return i;
+            }
+        }
+        self.buf.len()
+    }
 }
 
 impl From<&String> for Row {
     fn from(slice: &String) -> Self {
         let mut buf = vec![];
-        for c in slice.trim_end().chars() {
+        for c in slice.chars() {
             buf.push(c);
         }
         //for grapheme in slice.graphemes(true) {
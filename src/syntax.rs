@@ -0,0 +1,578 @@
+use crate::Row;
+
+use std::fmt::{Display, Formatter, Result as FormatResult};
+use std::path::Path;
+
+// how many lines at the top and the bottom of a file are scanned for modelines.
+const MODELINE_SCAN_LINES: usize = 5;
+
+const C_KEY_WORD_1: [&str; 15] = [
+    "switch", "if", "while", "for", "break", "continue", "return", "else", "struct", "union",
+    "typedef", "static", "enum", "class", "case",
+];
+
+const C_KEY_WORD_2: [&str; 8] = [
+    "int", "long", "double", "float", "char", "unsigned", "signed", "void",
+];
+
+const RUST_KEY_WORD_1: [&str; 31] = [
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "fn", "for", "if",
+    "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self",
+    "Self", "static", "struct", "super", "trait", "type", "use", "where",
+];
+
+const RUST_KEY_WORD_2: [&str; 20] = [
+    "bool", "char", "str", "String", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16",
+    "u32", "u64", "u128", "usize", "f32", "f64", "Option", "Result",
+];
+
+const PYTHON_KEY_WORD_1: [&str; 33] = [
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+    "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+    "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield", "self",
+];
+
+const PYTHON_KEY_WORD_2: [&str; 11] = [
+    "int", "float", "str", "bool", "list", "dict", "set", "tuple", "None", "True", "False",
+];
+
+const SHELL_KEY_WORD_1: [&str; 18] = [
+    "if", "then", "else", "elif", "fi", "case", "esac", "for", "while", "until", "do", "done",
+    "in", "function", "select", "return", "break", "continue",
+];
+
+const SHELL_KEY_WORD_2: [&str; 10] = [
+    "echo", "export", "local", "readonly", "set", "unset", "shift", "source", "exit", "cd",
+];
+
+const C_CONSTANTS: [&str; 3] = ["NULL", "true", "false"];
+
+const RUST_CONSTANTS: [&str; 6] = ["true", "false", "None", "Some", "Ok", "Err"];
+
+const MAKEFILE_KEY_WORD_1: [&str; 10] = [
+    "ifeq", "ifneq", "ifdef", "ifndef", "else", "endif", "include", "define", "endef", "export",
+];
+
+const MAKEFILE_KEY_WORD_2: [&str; 3] = [".PHONY", ".SUFFIXES", ".DEFAULT"];
+
+const DOCKERFILE_KEY_WORD_1: [&str; 18] = [
+    "FROM",
+    "RUN",
+    "CMD",
+    "LABEL",
+    "EXPOSE",
+    "ENV",
+    "ADD",
+    "COPY",
+    "ENTRYPOINT",
+    "VOLUME",
+    "USER",
+    "WORKDIR",
+    "ARG",
+    "ONBUILD",
+    "STOPSIGNAL",
+    "HEALTHCHECK",
+    "SHELL",
+    "MAINTAINER",
+];
+
+const DOCKERFILE_KEY_WORD_2: [&str; 1] = ["AS"];
+
+// exact file names, checked before any pattern.
+const FILE_NAMES: [(&str, FileType); 12] = [
+    ("Makefile", FileType::Makefile),
+    ("makefile", FileType::Makefile),
+    ("GNUmakefile", FileType::Makefile),
+    ("Dockerfile", FileType::Dockerfile),
+    ("Containerfile", FileType::Dockerfile),
+    (".bashrc", FileType::Shell),
+    (".bash_profile", FileType::Shell),
+    (".bash_logout", FileType::Shell),
+    (".profile", FileType::Shell),
+    (".zshrc", FileType::Shell),
+    (".zprofile", FileType::Shell),
+    ("PKGBUILD", FileType::Shell),
+];
+
+// glob patterns matched against the file name (not the whole path).
+const FILE_PATTERNS: [(&str, FileType); 18] = [
+    ("*.c", FileType::C),
+    ("*.h", FileType::C),
+    ("*.cpp", FileType::C),
+    ("*.hpp", FileType::C),
+    ("*.cc", FileType::C),
+    ("*.rs", FileType::Rust),
+    ("*.py", FileType::Python),
+    ("*.pyw", FileType::Python),
+    ("*.sh", FileType::Shell),
+    ("*.bash", FileType::Shell),
+    ("*.zsh", FileType::Shell),
+    (".bashrc*", FileType::Shell),
+    (".zshrc*", FileType::Shell),
+    ("*.mk", FileType::Makefile),
+    ("*.mak", FileType::Makefile),
+    ("Makefile.*", FileType::Makefile),
+    ("Dockerfile.*", FileType::Dockerfile),
+    ("*.dockerfile", FileType::Dockerfile),
+];
+
+// interpreters named on the `#!` line.
+const INTERPRETERS: [(&str, FileType); 8] = [
+    ("sh", FileType::Shell),
+    ("bash", FileType::Shell),
+    ("zsh", FileType::Shell),
+    ("dash", FileType::Shell),
+    ("ksh", FileType::Shell),
+    ("python", FileType::Python),
+    ("make", FileType::Makefile),
+    ("rust-script", FileType::Rust),
+];
+
+/// How a language spells its preprocessor-like lines.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Preprocessor {
+    None,
+    /// `#include <stdio.h>`, `#define X` at the start of a line.
+    Directive,
+    /// `#[derive(Debug)]` and `#![allow(unused)]`.
+    Attribute,
+    /// `@property` at the start of a line.
+    Decorator,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FileType {
+    C,
+    Rust,
+    Python,
+    Shell,
+    Makefile,
+    Dockerfile,
+}
+
+impl Display for FileType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            FileType::C => write!(f, "C"),
+            FileType::Rust => write!(f, "Rust"),
+            FileType::Python => write!(f, "Python"),
+            FileType::Shell => write!(f, "Shell"),
+            FileType::Makefile => write!(f, "Makefile"),
+            FileType::Dockerfile => write!(f, "Dockerfile"),
+        }
+    }
+}
+
+impl FileType {
+    pub fn all() -> [FileType; 6] {
+        [
+            FileType::C,
+            FileType::Rust,
+            FileType::Python,
+            FileType::Shell,
+            FileType::Makefile,
+            FileType::Dockerfile,
+        ]
+    }
+
+    /// Looks a filetype up by the name a user (or a modeline) would type.
+    pub fn from_name(name: &str) -> Option<FileType> {
+        match name.trim().to_lowercase().as_str() {
+            "c" | "h" | "cpp" | "c++" => Some(FileType::C),
+            "rust" | "rs" => Some(FileType::Rust),
+            "python" | "python3" | "py" => Some(FileType::Python),
+            "sh" | "bash" | "zsh" | "shell" | "shell-script" => Some(FileType::Shell),
+            "make" | "makefile" | "makefile-gmake" => Some(FileType::Makefile),
+            "dockerfile" | "docker" => Some(FileType::Dockerfile),
+            _ => None,
+        }
+    }
+}
+
+pub struct EditorSyntax {
+    pub file_type: FileType,
+    /// `None` for filetypes that only have block comments.
+    pub singleline_comment_start: Option<String>,
+    pub multiline_comment_start: String,
+    pub multiline_comment_end: String,
+    pub keywords1: &'static [&'static str],
+    pub keywords2: &'static [&'static str],
+    pub constants: &'static [&'static str],
+    pub highlight_number: bool,
+    pub highlight_strings: bool,
+    pub highlight_operators: bool,
+    /// guess types (`CamelCase`, `size_t`) and constants (`ALL_CAPS`) from
+    /// how identifiers are spelled.
+    pub identifier_heuristics: bool,
+    /// `///`, `//!`, `/**` and `/*!` open doc comments.
+    pub doc_comments: bool,
+    pub preprocessor: Preprocessor,
+    /// characters that open (and close) a string.
+    pub string_quotes: &'static [char],
+    /// quotes whose contents are taken literally, without backslash escapes.
+    pub raw_quotes: &'static [char],
+    /// letters that may prefix a string (`b"..."`, `f'...'`).
+    pub string_prefixes: &'static str,
+    /// prefix letters that turn the following string raw (`r"..."`).
+    pub raw_prefixes: &'static str,
+    /// raw strings may be fenced with `#`s, as in `r#"..."#`.
+    pub hashed_raw_strings: bool,
+    /// `"""` and `'''` open strings that run until the same triple.
+    pub triple_quotes: bool,
+    /// `'` opens a char literal only when it is closed right away,
+    /// otherwise it is a lifetime or label.
+    pub char_literals: bool,
+    /// an unterminated string carries on to the next line.
+    pub multiline_strings: bool,
+    /// block comments nest, as in `/* /* */ */`.
+    pub nested_comments: bool,
+    /// the line comment only starts at the beginning of a word (`$#` is not one).
+    pub comment_needs_separator: bool,
+    /// characters that indent the next line when they end a line.
+    pub indent_openers: &'static [char],
+    /// characters that take a level of indentation off a blank line when
+    /// typed on it.
+    pub indent_closers: &'static [char],
+    /// pairs closed automatically when their opener is typed.
+    pub auto_pairs: &'static [(char, char)],
+}
+
+impl EditorSyntax {
+    pub fn new(file_type: FileType) -> Self {
+        let base = Self {
+            file_type,
+            singleline_comment_start: Some(String::from("#")),
+            multiline_comment_start: String::new(),
+            multiline_comment_end: String::new(),
+            keywords1: &[],
+            keywords2: &[],
+            constants: &[],
+            highlight_number: true,
+            highlight_strings: true,
+            highlight_operators: true,
+            identifier_heuristics: false,
+            doc_comments: false,
+            preprocessor: Preprocessor::None,
+            string_quotes: &['"', '\''],
+            raw_quotes: &[],
+            string_prefixes: "",
+            raw_prefixes: "",
+            hashed_raw_strings: false,
+            triple_quotes: false,
+            char_literals: false,
+            multiline_strings: false,
+            nested_comments: false,
+            comment_needs_separator: false,
+            indent_openers: &[],
+            indent_closers: &[],
+            auto_pairs: &[],
+        };
+        match file_type {
+            FileType::C => Self {
+                singleline_comment_start: Some(String::from("//")),
+                multiline_comment_start: String::from("/*"),
+                multiline_comment_end: String::from("*/"),
+                keywords1: &C_KEY_WORD_1,
+                keywords2: &C_KEY_WORD_2,
+                constants: &C_CONSTANTS,
+                identifier_heuristics: true,
+                doc_comments: true,
+                preprocessor: Preprocessor::Directive,
+                string_prefixes: "LuU8",
+                indent_openers: &['{', '(', '['],
+                indent_closers: &['}', ')', ']'],
+                auto_pairs: &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')],
+                ..base
+            },
+            FileType::Rust => Self {
+                singleline_comment_start: Some(String::from("//")),
+                multiline_comment_start: String::from("/*"),
+                multiline_comment_end: String::from("*/"),
+                keywords1: &RUST_KEY_WORD_1,
+                keywords2: &RUST_KEY_WORD_2,
+                constants: &RUST_CONSTANTS,
+                identifier_heuristics: true,
+                doc_comments: true,
+                preprocessor: Preprocessor::Attribute,
+                string_quotes: &['"'],
+                string_prefixes: "bc",
+                raw_prefixes: "r",
+                hashed_raw_strings: true,
+                char_literals: true,
+                multiline_strings: true,
+                nested_comments: true,
+                indent_openers: &['{', '(', '['],
+                indent_closers: &['}', ')', ']'],
+                // no `'`, which starts lifetimes more often than chars.
+                auto_pairs: &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')],
+                ..base
+            },
+            FileType::Python => Self {
+                keywords1: &PYTHON_KEY_WORD_1,
+                keywords2: &PYTHON_KEY_WORD_2,
+                identifier_heuristics: true,
+                preprocessor: Preprocessor::Decorator,
+                string_prefixes: "bBfFuU",
+                raw_prefixes: "rR",
+                triple_quotes: true,
+                indent_openers: &[':', '(', '[', '{'],
+                indent_closers: &[')', ']', '}'],
+                auto_pairs: &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')],
+                ..base
+            },
+            FileType::Shell => Self {
+                keywords1: &SHELL_KEY_WORD_1,
+                keywords2: &SHELL_KEY_WORD_2,
+                string_quotes: &['"', '\'', '`'],
+                raw_quotes: &['\''],
+                highlight_operators: false,
+                comment_needs_separator: true,
+                indent_openers: &['{'],
+                indent_closers: &['}'],
+                auto_pairs: &[
+                    ('(', ')'),
+                    ('[', ']'),
+                    ('{', '}'),
+                    ('"', '"'),
+                    ('\'', '\''),
+                    ('`', '`'),
+                ],
+                ..base
+            },
+            FileType::Makefile => Self {
+                keywords1: &MAKEFILE_KEY_WORD_1,
+                keywords2: &MAKEFILE_KEY_WORD_2,
+                highlight_number: false,
+                highlight_operators: false,
+                auto_pairs: &[('(', ')'), ('{', '}')],
+                ..base
+            },
+            FileType::Dockerfile => Self {
+                keywords1: &DOCKERFILE_KEY_WORD_1,
+                keywords2: &DOCKERFILE_KEY_WORD_2,
+                highlight_operators: false,
+                comment_needs_separator: true,
+                auto_pairs: &[('[', ']'), ('"', '"')],
+                ..base
+            },
+        }
+    }
+}
+
+/// Runs the detection pipeline: modelines, exact file names, glob patterns
+/// and finally the `#!` line of the first row.
+pub fn detect_file_type(file_name: Option<&str>, rows: &[Row]) -> Option<FileType> {
+    if let Some(file_type) = detect_from_modeline(rows) {
+        return Some(file_type);
+    }
+    if let Some(name) = file_name.and_then(|f| Path::new(f).file_name()?.to_str()) {
+        for (exact, file_type) in FILE_NAMES.iter() {
+            if *exact == name {
+                return Some(*file_type);
+            }
+        }
+        for (pattern, file_type) in FILE_PATTERNS.iter() {
+            if glob_match(pattern, name) {
+                return Some(*file_type);
+            }
+        }
+    }
+    rows.first().and_then(detect_from_shebang)
+}
+
+fn detect_from_shebang(row: &Row) -> Option<FileType> {
+    let line = row.buf.iter().collect::<String>();
+    let command = line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // `#!/usr/bin/env -S python3 -u`
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(name, _)| *name == program)
+        .map(|(_, file_type)| *file_type)
+}
+
+fn detect_from_modeline(rows: &[Row]) -> Option<FileType> {
+    let head = rows.iter().take(MODELINE_SCAN_LINES);
+    let tail = rows
+        .iter()
+        .skip(MODELINE_SCAN_LINES.max(rows.len().saturating_sub(MODELINE_SCAN_LINES)));
+    for row in head.chain(tail) {
+        let line = row.buf.iter().collect::<String>();
+        if let Some(file_type) = parse_vim_modeline(&line).or_else(|| parse_emacs_modeline(&line)) {
+            return Some(file_type);
+        }
+    }
+    None
+}
+
+// `vim: set ft=rust:`, `vi: filetype=python`, `ex: ft=sh`
+fn parse_vim_modeline(line: &str) -> Option<FileType> {
+    let start = ["vim:", "vi:", "ex:"]
+        .iter()
+        .filter_map(|marker| {
+            line.find(marker).and_then(|i| {
+                let preceded_by_space = i == 0 || line[..i].ends_with(char::is_whitespace);
+                if preceded_by_space {
+                    Some(i + marker.len())
+                } else {
+                    None
+                }
+            })
+        })
+        .min()?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .filter_map(|option| {
+            let (key, value) = option.split_at(option.find('=')?);
+            match key {
+                "ft" | "filetype" | "syntax" | "syn" => Some(&value[1..]),
+                _ => None,
+            }
+        })
+        .find_map(FileType::from_name)
+}
+
+// `-*- mode: python -*-` or the short `-*- python -*-`
+fn parse_emacs_modeline(line: &str) -> Option<FileType> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let body = line[start..end].trim();
+    if !body.contains(':') {
+        return FileType::from_name(body);
+    }
+    body.split(';')
+        .filter_map(|variable| {
+            let mut pair = variable.splitn(2, ':');
+            match pair.next()?.trim().to_lowercase().as_str() {
+                "mode" => pair.next(),
+                _ => None,
+            }
+        })
+        .find_map(FileType::from_name)
+}
+
+// supports `*` (any run of characters) and `?` (any single character).
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rows(lines: &[&str]) -> Vec<Row> {
+        lines.iter().map(|l| Row::from(&String::from(*l))).collect()
+    }
+
+    #[test]
+    fn test_detect_by_file_name() {
+        let empty = rows(&[]);
+        assert_eq!(
+            detect_file_type(Some("src/Makefile"), &empty),
+            Some(FileType::Makefile)
+        );
+        assert_eq!(
+            detect_file_type(Some("Dockerfile"), &empty),
+            Some(FileType::Dockerfile)
+        );
+        assert_eq!(
+            detect_file_type(Some("/home/me/.bashrc"), &empty),
+            Some(FileType::Shell)
+        );
+        assert_eq!(
+            detect_file_type(Some("hello.cpp"), &empty),
+            Some(FileType::C)
+        );
+        assert_eq!(
+            detect_file_type(Some("main.rs"), &empty),
+            Some(FileType::Rust)
+        );
+        assert_eq!(
+            detect_file_type(Some("Dockerfile.dev"), &empty),
+            Some(FileType::Dockerfile)
+        );
+        assert_eq!(detect_file_type(Some("notes.txt"), &empty), None);
+        assert_eq!(detect_file_type(None, &empty), None);
+    }
+
+    #[test]
+    fn test_detect_by_shebang() {
+        assert_eq!(
+            detect_file_type(Some("deploy"), &rows(&["#!/bin/bash", "echo hi"])),
+            Some(FileType::Shell)
+        );
+        assert_eq!(
+            detect_file_type(Some("tool"), &rows(&["#!/usr/bin/env python3"])),
+            Some(FileType::Python)
+        );
+        assert_eq!(
+            detect_file_type(None, &rows(&["#!/usr/bin/env -S python3.11 -u"])),
+            Some(FileType::Python)
+        );
+        assert_eq!(detect_file_type(None, &rows(&["#!/usr/bin/perl"])), None);
+        assert_eq!(detect_file_type(None, &rows(&["# not a shebang"])), None);
+    }
+
+    #[test]
+    fn test_detect_by_modeline() {
+        assert_eq!(
+            detect_file_type(Some("a.txt"), &rows(&["# vim: set ft=python:"])),
+            Some(FileType::Python)
+        );
+        assert_eq!(
+            detect_file_type(Some("a.c"), &rows(&["/* vi: filetype=rust */"])),
+            Some(FileType::Rust)
+        );
+        assert_eq!(
+            detect_file_type(None, &rows(&["# -*- mode: sh; tab-width: 4 -*-"])),
+            Some(FileType::Shell)
+        );
+        assert_eq!(
+            detect_file_type(None, &rows(&["// -*- C++ -*-"])),
+            Some(FileType::C)
+        );
+        let mut long_file = vec!["x"; 20];
+        long_file.push("# vim: ft=make");
+        assert_eq!(
+            detect_file_type(None, &rows(&long_file)),
+            Some(FileType::Makefile)
+        );
+        // `vim:` inside a word is not a modeline.
+        assert_eq!(detect_file_type(None, &rows(&["novim: ft=rust"])), None);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "main.rsx"));
+        assert!(glob_match("Makefile.*", "Makefile.am"));
+        assert!(glob_match("?.c", "a.c"));
+        assert!(!glob_match("?.c", "ab.c"));
+        assert!(glob_match("*a*b", "xaxxb"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::theme::{ColorSupport, Style};
 use crate::Position;
 
 use std::io::{self, stdout, Write};
@@ -81,4 +82,8 @@ impl Terminal {
     pub fn reset_bg_color() {
         print!("{}", color::Bg(color::Reset))
     }
+
+    pub fn set_style(style: &Style, color_support: ColorSupport) {
+        print!("{}", style.escape(color_support))
+    }
 }
@@ -0,0 +1,493 @@
+use crate::config::{config_dir, parse_key_values};
+use crate::Highlight;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use termion::{color, style};
+
+pub const DEFAULT_THEME: &str = "default";
+const THEME_DIR_NAME: &str = "themes";
+const THEME_EXTENSION: &str = "theme";
+
+// the 16 standard colours, as xterm draws them.
+const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const ANSI_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How many colours the terminal can show.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorSupport {
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default().to_lowercase();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Color {
+    Rgb(u8, u8, u8),
+    Ansi(u8),
+}
+
+impl Color {
+    /// `#rrggbb`, a 0-255 palette index or one of the 16 colour names.
+    pub fn parse(s: &str) -> Option<Color> {
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 {
+                return None;
+            }
+            let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+            return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+        }
+        if let Ok(index) = s.parse::<u8>() {
+            return Some(Color::Ansi(index));
+        }
+        ANSI_NAMES
+            .iter()
+            .position(|name| *name == s)
+            .map(|i| Color::Ansi(i as u8))
+    }
+
+    /// Lowers the colour to something the terminal can show.
+    pub fn downsample(self, support: ColorSupport) -> Color {
+        match (self, support) {
+            (_, ColorSupport::TrueColor) => self,
+            (Color::Rgb(r, g, b), ColorSupport::Ansi256) => Color::Ansi(rgb_to_256(r, g, b)),
+            (Color::Ansi(_), ColorSupport::Ansi256) => self,
+            (Color::Ansi(index), ColorSupport::Ansi16) if index < 16 => self,
+            (Color::Ansi(index), ColorSupport::Ansi16) => {
+                let (r, g, b) = ansi_to_rgb(index);
+                Color::Ansi(rgb_to_16(r, g, b))
+            }
+            (Color::Rgb(r, g, b), ColorSupport::Ansi16) => Color::Ansi(rgb_to_16(r, g, b)),
+        }
+    }
+
+    fn fg_escape(self) -> String {
+        match self {
+            Color::Rgb(r, g, b) => color::Fg(color::Rgb(r, g, b)).to_string(),
+            Color::Ansi(index) => color::Fg(color::AnsiValue(index)).to_string(),
+        }
+    }
+
+    fn bg_escape(self) -> String {
+        match self {
+            Color::Rgb(r, g, b) => color::Bg(color::Rgb(r, g, b)).to_string(),
+            Color::Ansi(index) => color::Bg(color::AnsiValue(index)).to_string(),
+        }
+    }
+}
+
+fn ansi_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_16[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |v: u8| {
+        (0..6)
+            .min_by_key(|i| (i32::from(CUBE_LEVELS[*i]) - i32::from(v)).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * nearest_level(r) + 6 * nearest_level(g) + nearest_level(b);
+    let gray_level = ((i32::from(r) + i32::from(g) + i32::from(b)) / 3 - 8).clamp(0, 230) / 10;
+    let gray = 232 + gray_level as u8;
+    if distance(ansi_to_rgb(gray), (r, g, b)) < distance(ansi_to_rgb(cube), (r, g, b)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn rgb_to_16(r: u8, g: u8, b: u8) -> u8 {
+    (0..16)
+        .min_by_key(|i| distance(ANSI_16[*i], (r, g, b)))
+        .unwrap_or(7) as u8
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    pub fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            ..Self::default()
+        }
+    }
+
+    /// `fg=#ff0000 bg=4 bold italic underline`, in any order.
+    pub fn parse(s: &str) -> Result<Style, String> {
+        let mut style = Style::default();
+        for word in s.split_whitespace() {
+            match word {
+                "bold" => style.bold = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                _ => {
+                    let (key, value) = match word.find('=') {
+                        Some(at) => (&word[..at], &word[at + 1..]),
+                        None => ("fg", word),
+                    };
+                    let color =
+                        Color::parse(value).ok_or_else(|| format!("bad colour `{}`", value))?;
+                    match key {
+                        "fg" => style.fg = Some(color),
+                        "bg" => style.bg = Some(color),
+                        _ => return Err(format!("unknown attribute `{}`", key)),
+                    }
+                }
+            }
+        }
+        Ok(style)
+    }
+
+    /// `top` drawn over this style: its colours win where it has them and
+    /// attributes add up.
+    pub fn overlaid(self, top: Style) -> Style {
+        Style {
+            fg: top.fg.or(self.fg),
+            bg: top.bg.or(self.bg),
+            bold: self.bold || top.bold,
+            italic: self.italic || top.italic,
+            underline: self.underline || top.underline,
+        }
+    }
+
+    /// The escape sequence that switches the terminal to this style, starting
+    /// from a clean slate.
+    pub fn escape(&self, support: ColorSupport) -> String {
+        let mut escape = style::Reset.to_string();
+        if self.bold {
+            escape.push_str(style::Bold.as_ref());
+        }
+        if self.italic {
+            escape.push_str(style::Italic.as_ref());
+        }
+        if self.underline {
+            escape.push_str(style::Underline.as_ref());
+        }
+        if let Some(fg) = self.fg {
+            escape.push_str(&fg.downsample(support).fg_escape());
+        }
+        if let Some(bg) = self.bg {
+            escape.push_str(&bg.downsample(support).bg_escape());
+        }
+        escape
+    }
+}
+
+/// Parts of the screen that are not syntax.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum UiElement {
+    StatusBar,
+    MessageBar,
+    Selection,
+    LineNumber,
+    SearchMatch,
+    SearchCurrent,
+    MatchingBracket,
+    Cursor,
+}
+
+impl UiElement {
+    fn from_name(name: &str) -> Option<UiElement> {
+        match name {
+            "status_bar" => Some(UiElement::StatusBar),
+            "message_bar" => Some(UiElement::MessageBar),
+            "selection" => Some(UiElement::Selection),
+            "line_number" => Some(UiElement::LineNumber),
+            // `match` was a syntax class before search matches became an overlay.
+            "search_match" | "match" => Some(UiElement::SearchMatch),
+            "search_current" => Some(UiElement::SearchCurrent),
+            "matching_bracket" => Some(UiElement::MatchingBracket),
+            "cursor" => Some(UiElement::Cursor),
+            _ => None,
+        }
+    }
+}
+
+pub struct Theme {
+    highlights: HashMap<Highlight, Style>,
+    ui: HashMap<UiElement, Style>,
+}
+
+impl Default for Theme {
+    // the colours the editor has always used.
+    fn default() -> Self {
+        let highlights = [
+            (Highlight::Normal, Style::fg(Color::Ansi(7))),
+            (Highlight::Number, Style::fg(Color::Ansi(1))),
+            (Highlight::String, Style::fg(Color::Ansi(5))),
+            (Highlight::Comment, Style::fg(Color::Ansi(6))),
+            (Highlight::MultiComment, Style::fg(Color::Ansi(6))),
+            (
+                Highlight::DocComment,
+                Style {
+                    italic: true,
+                    ..Style::fg(Color::Ansi(6))
+                },
+            ),
+            (Highlight::Keyword1, Style::fg(Color::Ansi(2))),
+            (Highlight::Keyword2, Style::fg(Color::Ansi(3))),
+            (Highlight::Function, Style::fg(Color::Ansi(12))),
+            (Highlight::Type, Style::fg(Color::Ansi(11))),
+            (Highlight::Constant, Style::fg(Color::Ansi(9))),
+            (Highlight::Operator, Style::fg(Color::Ansi(15))),
+            (Highlight::Preprocessor, Style::fg(Color::Ansi(13))),
+            (Highlight::Escape, Style::fg(Color::Ansi(14))),
+            (
+                Highlight::Todo,
+                Style {
+                    bg: Some(Color::Ansi(3)),
+                    bold: true,
+                    ..Style::fg(Color::Ansi(0))
+                },
+            ),
+            (
+                Highlight::Diagnostic,
+                Style {
+                    underline: true,
+                    ..Style::fg(Color::Ansi(9))
+                },
+            ),
+        ];
+        let bar = Style {
+            fg: Some(Color::Ansi(0)),
+            bg: Some(Color::Ansi(13)),
+            ..Style::default()
+        };
+        let ui = [
+            (UiElement::StatusBar, bar),
+            (UiElement::MessageBar, bar),
+            (
+                UiElement::Selection,
+                Style {
+                    fg: Some(Color::Ansi(0)),
+                    bg: Some(Color::Ansi(7)),
+                    ..Style::default()
+                },
+            ),
+            (UiElement::LineNumber, Style::fg(Color::Ansi(8))),
+            // background only, so the syntax colour shows through.
+            (
+                UiElement::SearchMatch,
+                Style {
+                    bg: Some(Color::Ansi(4)),
+                    ..Style::default()
+                },
+            ),
+            (
+                UiElement::SearchCurrent,
+                Style {
+                    fg: Some(Color::Ansi(0)),
+                    bg: Some(Color::Ansi(11)),
+                    bold: true,
+                    ..Style::default()
+                },
+            ),
+            (
+                UiElement::MatchingBracket,
+                Style {
+                    bg: Some(Color::Ansi(8)),
+                    bold: true,
+                    ..Style::default()
+                },
+            ),
+            (
+                UiElement::Cursor,
+                Style {
+                    fg: Some(Color::Ansi(0)),
+                    bg: Some(Color::Ansi(7)),
+                    ..Style::default()
+                },
+            ),
+        ];
+        Self {
+            highlights: highlights.iter().copied().collect(),
+            ui: ui.iter().copied().collect(),
+        }
+    }
+}
+
+impl Theme {
+    /// Loads `<config dir>/themes/<name>.theme`. Anything the file leaves out
+    /// keeps the default look.
+    pub fn load(name: &str) -> Result<Theme, String> {
+        if name == DEFAULT_THEME {
+            return Ok(Theme::default());
+        }
+        let path = config_dir()
+            .ok_or_else(|| String::from("no config directory"))?
+            .join(THEME_DIR_NAME)
+            .join(format!("{}.{}", name, THEME_EXTENSION));
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("theme `{}`: {}: {}", name, path.display(), e))?;
+        Theme::parse(&text).map_err(|e| format!("theme `{}`: {}", name, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Theme, String> {
+        let mut theme = Theme::default();
+        for (line, key, value) in parse_key_values(text)? {
+            let style = Style::parse(&value).map_err(|e| format!("line {}: {}", line, e))?;
+            if let Some(highlight) = Highlight::from_name(&key) {
+                theme.highlights.insert(highlight, style);
+            } else if let Some(element) = UiElement::from_name(&key) {
+                theme.ui.insert(element, style);
+            } else {
+                return Err(format!("line {}: unknown element `{}`", line, key));
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn highlight_style(&self, highlight: Highlight) -> Style {
+        self.highlights.get(&highlight).copied().unwrap_or_default()
+    }
+
+    pub fn ui_style(&self, element: UiElement) -> Style {
+        self.ui.get(&element).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(Color::parse("#ff8000"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(Color::parse("208"), Some(Color::Ansi(208)));
+        assert_eq!(Color::parse("bright_blue"), Some(Color::Ansi(12)));
+        assert_eq!(Color::parse("#ff80"), None);
+        assert_eq!(Color::parse("256"), None);
+        assert_eq!(Color::parse("mauve"), None);
+    }
+
+    #[test]
+    fn test_downsample() {
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(orange.downsample(ColorSupport::TrueColor), orange);
+        assert_eq!(orange.downsample(ColorSupport::Ansi256), Color::Ansi(208));
+        assert_eq!(
+            Color::Rgb(250, 5, 5).downsample(ColorSupport::Ansi16),
+            Color::Ansi(9)
+        );
+        assert_eq!(
+            Color::Rgb(128, 128, 128).downsample(ColorSupport::Ansi256),
+            Color::Ansi(244)
+        );
+        assert_eq!(
+            Color::Ansi(196).downsample(ColorSupport::Ansi16),
+            Color::Ansi(9)
+        );
+        assert_eq!(
+            Color::Ansi(3).downsample(ColorSupport::Ansi16),
+            Color::Ansi(3)
+        );
+    }
+
+    #[test]
+    fn test_parse_theme() {
+        let theme = Theme::parse(
+            "# a comment\nkeyword1 = fg=#00ff00 bold\nstatus_bar = fg=black bg=#444444 italic\n",
+        )
+        .unwrap();
+        assert_eq!(
+            theme.highlight_style(Highlight::Keyword1),
+            Style {
+                fg: Some(Color::Rgb(0, 255, 0)),
+                bold: true,
+                ..Style::default()
+            }
+        );
+        assert_eq!(
+            theme.ui_style(UiElement::StatusBar).bg,
+            Some(Color::Rgb(68, 68, 68))
+        );
+        // untouched classes keep the default look.
+        assert_eq!(
+            theme.highlight_style(Highlight::Number),
+            Style::fg(Color::Ansi(1))
+        );
+
+        assert!(Theme::parse("keyword9 = red").is_err());
+        assert!(Theme::parse("todo = fg=red\nmatch = bg=blue").is_ok());
+        assert!(Theme::parse("string = fg=#zzzzzz").is_err());
+        assert!(Theme::parse("string red").is_err());
+    }
+}
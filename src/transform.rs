@@ -0,0 +1,192 @@
+use crate::indent::leading_whitespace;
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// The words `sort` takes, in any combination.
+pub const SORT_OPTIONS: &[&str] = &["reverse", "numeric", "ignore-case"];
+
+/// How `sort` orders lines.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SortOptions {
+    pub reverse: bool,
+    /// by the number the line starts with; lines without one come first.
+    pub numeric: bool,
+    pub ignore_case: bool,
+}
+
+impl SortOptions {
+    pub fn parse(words: &[String]) -> Result<SortOptions, String> {
+        let mut options = SortOptions::default();
+        for word in words {
+            match word.as_str() {
+                "reverse" => options.reverse = true,
+                "numeric" => options.numeric = true,
+                "ignore-case" => options.ignore_case = true,
+                _ => return Err(format!("unknown sort option `{}`", word)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// the number at the start of `line`, after any indentation.
+fn leading_number(line: &[char]) -> Option<f64> {
+    let line = &line[leading_whitespace(line).len()..];
+    let mut end = 0;
+    let mut seen_point = false;
+    for (i, c) in line.iter().enumerate() {
+        match c {
+            '-' | '+' if i == 0 => {}
+            '.' if !seen_point => seen_point = true,
+            c if c.is_ascii_digit() => {}
+            _ => break,
+        }
+        end = i + 1;
+    }
+    line[..end].iter().collect::<String>().parse().ok()
+}
+
+/// Sorts `lines` stably, so equal lines keep their order.
+pub fn sort_lines(lines: &mut [Vec<char>], options: SortOptions) {
+    let key = |line: &Vec<char>| -> Vec<char> {
+        if options.ignore_case {
+            line.iter().flat_map(|c| c.to_lowercase()).collect()
+        } else {
+            line.clone()
+        }
+    };
+    lines.sort_by(|a, b| {
+        let order = if options.numeric {
+            match (leading_number(a), leading_number(b)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            }
+        } else {
+            Ordering::Equal
+        };
+        let order = order.then_with(|| key(a).cmp(&key(b)));
+        if options.reverse {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+}
+
+/// `lines` without the repeats of any line, keeping the first of each.
+pub fn unique_lines(lines: Vec<Vec<char>>) -> Vec<Vec<char>> {
+    let mut seen = HashSet::new();
+    lines
+        .into_iter()
+        .filter(|line| seen.insert(line.clone()))
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Case {
+    Upper,
+    Lower,
+    /// each word capitalized and the rest of it lowercase.
+    Title,
+}
+
+pub fn change_case(text: &str, case: Case) -> String {
+    match case {
+        Case::Upper => text.to_uppercase(),
+        Case::Lower => text.to_lowercase(),
+        Case::Title => {
+            let mut title = String::new();
+            let mut in_word = false;
+            for c in text.chars() {
+                if in_word {
+                    title.extend(c.to_lowercase());
+                } else {
+                    title.extend(c.to_uppercase());
+                }
+                in_word = c.is_alphanumeric() || c == '\'';
+            }
+            title
+        }
+    }
+}
+
+pub fn trim_trailing_whitespace(line: &[char]) -> Vec<char> {
+    let end = line
+        .iter()
+        .rposition(|c| !c.is_whitespace())
+        .map_or(0, |i| i + 1);
+    line[..end].to_vec()
+}
+
+/// `line` with its indentation rewritten in tabs (and spaces for what is
+/// left over) or only spaces, keeping the width it has with tab stops
+/// every `tab_width` columns.
+pub fn retab(line: &[char], tabs: bool, tab_width: usize) -> Vec<char> {
+    let indent = leading_whitespace(line);
+    let width = indent.iter().fold(0, |column, c| match c {
+        '\t' => column + tab_width - column % tab_width,
+        _ => column + 1,
+    });
+    let mut retabbed = if tabs {
+        let mut indent = vec!['\t'; width / tab_width];
+        indent.extend(vec![' '; width % tab_width]);
+        indent
+    } else {
+        vec![' '; width]
+    };
+    retabbed.extend_from_slice(&line[indent.len()..]);
+    retabbed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chars(lines: &[&str]) -> Vec<Vec<char>> {
+        lines.iter().map(|line| line.chars().collect()).collect()
+    }
+
+    #[test]
+    fn test_sort_and_unique() {
+        let sorted = |lines: &[&str], words: &[&str]| {
+            let words = words.iter().map(|w| String::from(*w)).collect::<Vec<_>>();
+            let mut lines = chars(lines);
+            sort_lines(&mut lines, SortOptions::parse(&words).unwrap());
+            lines
+        };
+        let lines = ["10 b", "9 a", "B", "a", "-1.5"];
+        assert_eq!(
+            sorted(&lines, &[]),
+            chars(&["-1.5", "10 b", "9 a", "B", "a"])
+        );
+        assert_eq!(
+            sorted(&lines, &["numeric"]),
+            chars(&["B", "a", "-1.5", "9 a", "10 b"])
+        );
+        assert_eq!(
+            sorted(&lines, &["ignore-case", "reverse"]),
+            chars(&["B", "a", "9 a", "10 b", "-1.5"])
+        );
+        assert!(SortOptions::parse(&[String::from("fast")]).is_err());
+
+        assert_eq!(
+            unique_lines(chars(&["a", "b", "a", "", "b", ""])),
+            chars(&["a", "b", ""])
+        );
+    }
+
+    #[test]
+    fn test_case_and_whitespace() {
+        assert_eq!(change_case("don't STOP-me", Case::Title), "Don't Stop-Me");
+        assert_eq!(change_case("straße", Case::Upper), "STRASSE");
+        assert_eq!(
+            trim_trailing_whitespace(&chars(&["a b \t "])[0]),
+            chars(&["a b"])[0]
+        );
+        let line = &chars(&["  \t  x\t"])[0];
+        assert_eq!(retab(line, false, 4), chars(&["      x\t"])[0]);
+        assert_eq!(retab(line, true, 4), chars(&["\t  x\t"])[0]);
+        assert_eq!(retab(&chars(&["\t\tx"])[0], false, 2), chars(&["    x"])[0]);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::document::EditRange;
+use crate::syntax::FileType;
+use crate::Document;
+use crate::Highlight;
+
+use std::collections::HashSet;
+use std::ops::Range;
+use tree_sitter::{InputEdit, Language, Parser, Point, Query, QueryCursor, Tree};
+
+/// Highlights a document by parsing it with a tree-sitter grammar bundled at
+/// compile time, re-parsing and re-highlighting incrementally from the edits
+/// `Document` reports.
+pub struct TreeSitterHighlighter {
+    parser: Parser,
+    query: Query,
+    tree: Option<Tree>,
+    // the text of the last parse, one `\n` terminated line per row, kept in
+    // step with the document's edits.
+    source: String,
+    row_offsets: Vec<usize>,
+}
+
+impl TreeSitterHighlighter {
+    pub fn new(file_type: FileType) -> Option<Self> {
+        let (language, highlights): (Language, &str) = match file_type {
+            FileType::C => (
+                tree_sitter_c::LANGUAGE.into(),
+                tree_sitter_c::HIGHLIGHT_QUERY,
+            ),
+            FileType::Rust => (
+                tree_sitter_rust::LANGUAGE.into(),
+                tree_sitter_rust::HIGHLIGHTS_QUERY,
+            ),
+            FileType::Python => (
+                tree_sitter_python::LANGUAGE.into(),
+                tree_sitter_python::HIGHLIGHTS_QUERY,
+            ),
+            FileType::Shell => (
+                tree_sitter_bash::LANGUAGE.into(),
+                tree_sitter_bash::HIGHLIGHT_QUERY,
+            ),
+            FileType::Makefile | FileType::Dockerfile => return None,
+        };
+        let mut parser = Parser::new();
+        parser.set_language(&language).ok()?;
+        let query = Query::new(&language, highlights).ok()?;
+        Some(Self {
+            parser,
+            query,
+            tree: None,
+            source: String::new(),
+            row_offsets: vec![],
+        })
+    }
+
+    /// Re-parses what changed since the last call and fills in
+    /// `Row::highlight` for the rows whose highlighting may have changed
+    /// and the `visible` ones. Other rows keep what they had.
+    pub fn update(&mut self, document: &mut Document, tab_stop: usize, visible: Range<usize>) {
+        let edit = document.take_edit();
+        let mut stale = vec![visible];
+        match (edit, &self.tree) {
+            (None, Some(_)) => {}
+            (Some(edit), Some(_)) => {
+                let input_edit = self.apply_edit(edit, document);
+                let mut old_tree = self.tree.take();
+                if let Some(tree) = &mut old_tree {
+                    tree.edit(&input_edit);
+                }
+                self.tree = self.parser.parse(&self.source, old_tree.as_ref());
+                stale.push(edit.start..edit.new_end);
+                // an edit can change the highlighting of text it didn't
+                // touch, like everything after an opened comment.
+                if let (Some(old), Some(new)) = (&old_tree, &self.tree) {
+                    for range in old.changed_ranges(new) {
+                        stale.push(self.row_at(range.start_byte)..self.row_at(range.end_byte) + 1);
+                    }
+                }
+            }
+            (_, None) => {
+                let (source, row_offsets) = Self::source(document);
+                self.source = source;
+                self.row_offsets = row_offsets;
+                self.tree = self.parser.parse(&self.source, None);
+            }
+        }
+        for row in document.rows.iter_mut() {
+            if row.highlight.len() != row.render.len() {
+                row.highlight.resize(row.render.len(), Highlight::Normal);
+            }
+        }
+        for rows in merge(stale, document.len()) {
+            self.highlight_rows(document, tab_stop, rows);
+        }
+    }
+
+    fn source(document: &Document) -> (String, Vec<usize>) {
+        let mut source = String::new();
+        let mut row_offsets = Vec::with_capacity(document.len());
+        for row in &document.rows {
+            row_offsets.push(source.len());
+            source.extend(row.buf.iter());
+            source.push('\n');
+        }
+        (source, row_offsets)
+    }
+
+    // where row `y` starts in the source, or its end for rows past the last.
+    fn offset(&self, y: usize) -> usize {
+        self.row_offsets
+            .get(y)
+            .copied()
+            .unwrap_or(self.source.len())
+    }
+
+    // the row the byte at `offset` is on.
+    fn row_at(&self, offset: usize) -> usize {
+        self.row_offsets
+            .partition_point(|start| *start <= offset)
+            .saturating_sub(1)
+    }
+
+    /// Splices the rows `edit` changed into the source, returning the edit
+    /// as tree-sitter needs it.
+    fn apply_edit(&mut self, edit: EditRange, document: &Document) -> InputEdit {
+        let start_byte = self.offset(edit.start);
+        let old_end_byte = self.offset(edit.old_end);
+        let mut text = String::new();
+        let mut offsets = vec![];
+        for row in &document.rows[edit.start..edit.new_end] {
+            offsets.push(start_byte + text.len());
+            text.extend(row.buf.iter());
+            text.push('\n');
+        }
+        let new_end_byte = start_byte + text.len();
+        self.source.replace_range(start_byte..old_end_byte, &text);
+        let tail = self
+            .row_offsets
+            .split_off(edit.old_end.min(self.row_offsets.len()));
+        self.row_offsets.truncate(edit.start);
+        self.row_offsets.extend(offsets);
+        self.row_offsets.extend(
+            tail.into_iter()
+                .map(|offset| offset + new_end_byte - old_end_byte),
+        );
+        InputEdit {
+            start_byte,
+            old_end_byte,
+            new_end_byte,
+            start_position: Point::new(edit.start, 0),
+            old_end_position: Point::new(edit.old_end, 0),
+            new_end_position: Point::new(edit.new_end, 0),
+        }
+    }
+
+    /// Runs the query over `rows` only and highlights them from scratch.
+    fn highlight_rows(&self, document: &mut Document, tab_stop: usize, rows: Range<usize>) {
+        for row in &mut document.rows[rows.clone()] {
+            row.highlight = vec![Highlight::Normal; row.render.len()];
+        }
+        let tree = match &self.tree {
+            Some(tree) => tree,
+            None => return,
+        };
+
+        // the first pattern that captures a node wins, like tree-sitter-highlight.
+        let mut seen = HashSet::new();
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(self.offset(rows.start)..self.offset(rows.end));
+        let captures = cursor.captures(&self.query, tree.root_node(), self.source.as_bytes());
+        for (query_match, capture_index) in captures {
+            let capture = query_match.captures[capture_index];
+            let name = self.query.capture_names()[capture.index as usize];
+            let highlight = match capture_to_highlight(name) {
+                Some(highlight) => highlight,
+                None => continue,
+            };
+            if !seen.insert(capture.node.id()) {
+                continue;
+            }
+            let (start, end) = (capture.node.start_position(), capture.node.end_position());
+            for y in start.row.max(rows.start)..=end.row.min(rows.end.saturating_sub(1)) {
+                let line = &self.source[self.offset(y)..self.offset(y + 1) - 1];
+                let from = if y == start.row { start.column } else { 0 };
+                let to = if y == end.row { end.column } else { line.len() };
+                let row = &mut document.rows[y];
+                let from = row.render_index(char_index(line, from), tab_stop);
+                let to = row.render_index(char_index(line, to), tab_stop);
+                for h in row.highlight.iter_mut().take(to).skip(from) {
+                    *h = highlight;
+                }
+            }
+        }
+    }
+}
+
+// `ranges` of rows sorted, clamped to `len` and with overlapping ones joined.
+fn merge(mut ranges: Vec<Range<usize>>, len: usize) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = vec![];
+    for range in ranges {
+        let range = range.start.min(len)..range.end.min(len);
+        if range.is_empty() {
+            continue;
+        }
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+fn char_index(line: &str, byte: usize) -> usize {
+    let byte = byte.min(line.len());
+    line.char_indices().take_while(|(i, _)| *i < byte).count()
+}
+
+fn capture_to_highlight(name: &str) -> Option<Highlight> {
+    if name == "comment.documentation" {
+        return Some(Highlight::DocComment);
+    }
+    let class = name.split('.').next().unwrap_or(name);
+    match class {
+        "comment" => Some(Highlight::Comment),
+        "string" => Some(Highlight::String),
+        "escape" => Some(Highlight::Escape),
+        "number" => Some(Highlight::Number),
+        "keyword" => Some(Highlight::Keyword1),
+        "type" | "constructor" => Some(Highlight::Type),
+        "function" => Some(Highlight::Function),
+        "constant" => Some(Highlight::Constant),
+        "operator" => Some(Highlight::Operator),
+        "attribute" => Some(Highlight::Preprocessor),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Row;
+
+    fn document(lines: &[&str]) -> Document {
+        let mut document = Document::default();
+        for (i, line) in lines.iter().enumerate() {
+            let mut row = Row::from(&String::from(*line));
+            row.render = row.buf.clone();
+            document.insert_row(i, row);
+        }
+        document
+    }
+
+    #[test]
+    fn test_highlight_rust() {
+        let mut doc = document(&["fn main() {", "    let s = \"hi\"; // done", "}"]);
+        let mut highlighter = TreeSitterHighlighter::new(FileType::Rust).unwrap();
+        highlighter.update(&mut doc, 4, 0..10);
+        assert_eq!(doc.rows[0].highlight[0], Highlight::Keyword1);
+        assert_eq!(doc.rows[1].highlight[4], Highlight::Keyword1);
+        assert_eq!(doc.rows[1].highlight[12], Highlight::String);
+        assert_eq!(doc.rows[1].highlight[18], Highlight::Comment);
+        assert_eq!(doc.rows[1].highlight[8], Highlight::Normal);
+    }
+
+    #[test]
+    fn test_incremental_reparse() {
+        let mut doc = document(&["int a;", "int b;"]);
+        let mut highlighter = TreeSitterHighlighter::new(FileType::C).unwrap();
+        highlighter.update(&mut doc, 4, 0..10);
+
+        let mut comment = Row::from(&String::from("/* x */"));
+        comment.render = comment.buf.clone();
+        doc.insert_row(1, comment);
+        doc.replace_buf(0, "char a;".chars().collect()).unwrap();
+        doc.rows[0].render = doc.rows[0].buf.clone();
+        highlighter.update(&mut doc, 4, 0..10);
+
+        assert_eq!(doc.rows[0].highlight[0], Highlight::Type);
+        assert_eq!(doc.rows[1].highlight[0], Highlight::Comment);
+        assert_eq!(doc.rows[2].highlight[0], Highlight::Type);
+        assert!(!highlighter.tree.as_ref().unwrap().root_node().has_error());
+    }
+
+    #[test]
+    fn test_highlights_changed_and_visible_rows() {
+        let mut doc = document(&["int a;", "int b;", "int c;", "int d;"]);
+        let mut highlighter = TreeSitterHighlighter::new(FileType::C).unwrap();
+        highlighter.update(&mut doc, 4, 0..1);
+        assert_eq!(doc.rows[0].highlight[0], Highlight::Type);
+        assert_eq!(doc.rows[2].highlight, vec![Highlight::Normal; 6]);
+
+        // an edit below the screen is highlighted all the same.
+        doc.replace_buf(3, "char d;".chars().collect()).unwrap();
+        doc.rows[3].render = doc.rows[3].buf.clone();
+        doc.remove_row(1);
+        highlighter.update(&mut doc, 4, 0..1);
+        assert_eq!(doc.rows[2].highlight[0], Highlight::Type);
+        // without edits, rows off the screen are left alone.
+        doc.rows[2].highlight[0] = Highlight::Number;
+        highlighter.update(&mut doc, 4, 0..1);
+        assert_eq!(doc.rows[2].highlight[0], Highlight::Number);
+
+        let (source, row_offsets) = TreeSitterHighlighter::source(&doc);
+        assert_eq!(highlighter.source, source);
+        assert_eq!(highlighter.row_offsets, row_offsets);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::Row;
+
+const MAX_STEPS: usize = 1000;
+
+/// A change to the rows of a document. Applying one gives back the change
+/// that reverses it.
+#[derive(Debug, Clone)]
+pub enum Change {
+    Replace {
+        index: usize,
+        buf: Vec<char>,
+    },
+    /// the whole row goes back in, so what is rendered of it is kept.
+    Insert {
+        index: usize,
+        row: Row,
+    },
+    Remove {
+        index: usize,
+    },
+}
+
+/// The changes made by one command, to be undone together.
+#[derive(Debug)]
+pub struct Step {
+    /// tells the step apart from the others, and stays with it through
+    /// undoing and redoing.
+    pub id: usize,
+    /// changes reversing the step, to be applied last to first.
+    pub changes: Vec<Change>,
+    /// where the cursor was before the step.
+    pub cursor: (usize, usize),
+}
+
+#[derive(Default)]
+pub struct UndoHistory {
+    done: Vec<Step>,
+    undone: Vec<Step>,
+    // the cursor to give the next step, once a change starts it.
+    next_step: Option<(usize, usize)>,
+    // whether changes still go into the newest step; undoing or redoing
+    // closes it.
+    open: bool,
+    // how many groups are open; steps begun inside one join the group's.
+    groups: usize,
+    next_id: usize,
+    // the newest step when the document was saved.
+    saved: Option<usize>,
+}
+
+impl UndoHistory {
+    /// Makes the next change start a new step.
+    pub fn begin_step(&mut self, cursor: (usize, usize)) {
+        if self.groups == 0 {
+            self.next_step = Some(cursor);
+        }
+    }
+
+    /// Starts a step that everything up to the matching `end_group` is
+    /// part of.
+    pub fn begin_group(&mut self, cursor: (usize, usize)) {
+        self.begin_step(cursor);
+        self.groups += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.groups = self.groups.saturating_sub(1);
+    }
+
+    /// Adds the reverse of a change just made to the current step.
+    pub fn record(&mut self, undo: Change) {
+        self.undone.clear();
+        if self.next_step.is_some() || !self.open {
+            let cursor = self.next_step.take().unwrap_or_default();
+            self.next_id += 1;
+            self.done.push(Step {
+                id: self.next_id,
+                changes: vec![],
+                cursor,
+            });
+            if self.done.len() > MAX_STEPS {
+                self.done.remove(0);
+            }
+        }
+        self.open = true;
+        if let Some(step) = self.done.last_mut() {
+            step.changes.push(undo);
+        }
+    }
+
+    /// Remembers the current state as the saved one. The next change starts
+    /// a new step, so it can't slip into the saved step.
+    pub fn mark_saved(&mut self, cursor: (usize, usize)) {
+        self.saved = self.done.last().map(|step| step.id);
+        self.next_step = Some(cursor);
+    }
+
+    /// Whether undoing and redoing have come back to the saved state.
+    pub fn is_saved(&self) -> bool {
+        self.done.last().map(|step| step.id) == self.saved
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Step> {
+        self.next_step = None;
+        self.open = false;
+        self.done.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Step> {
+        self.next_step = None;
+        self.open = false;
+        self.undone.pop()
+    }
+
+    /// Keeps the reverse of an undone step so it can be redone.
+    pub fn push_redo(&mut self, step: Step) {
+        self.undone.push(step);
+    }
+
+    /// Keeps the reverse of a redone step so it can be undone again.
+    pub fn push_undo(&mut self, step: Step) {
+        self.done.push(step);
+    }
+}
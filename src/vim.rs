@@ -0,0 +1,560 @@
+use crate::Row;
+
+use termion::event::Key;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    /// `gg`/`G` with a count, 1-based.
+    Line(usize),
+    LastLine,
+}
+
+impl Motion {
+    /// Operators over these motions work on whole lines.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::Line(_) | Motion::LastLine
+        )
+    }
+
+    /// Operators over these motions include the character the motion ends on.
+    pub fn is_inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::LineEnd)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InsertAt {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    Move(Motion),
+    Operate(Operator, Motion),
+    /// `dd`, `cc` and `yy`.
+    OperateLines(Operator),
+    /// `d`, `c` or `y` on the visual selection.
+    OperateSelection(Operator),
+    DeleteChar,
+    Insert(InsertAt),
+    Paste {
+        after: bool,
+    },
+    ToggleVisual,
+    Repeat,
+    Undo,
+    CommandLine,
+    /// `q` and a register.
+    RecordMacro(char),
+    /// `@` and a register, or `@@` for the last macro.
+    PlayMacro(char),
+}
+
+impl Action {
+    /// Whether `.` should repeat this action.
+    pub fn is_change(self) -> bool {
+        match self {
+            Action::Operate(operator, _) | Action::OperateLines(operator) => {
+                operator != Operator::Yank
+            }
+            Action::DeleteChar | Action::Insert(_) | Action::Paste { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+    Pending,
+    Invalid,
+    Done { count: usize, action: Action },
+}
+
+/// Turns normal and visual mode keystrokes into counted actions.
+#[derive(Default)]
+pub struct Parser {
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    g: bool,
+    // `q` or `@`, waiting for the register.
+    macro_key: Option<char>,
+    // a `"` waiting for the register the action uses, and that register.
+    quote: bool,
+    register: Option<char>,
+}
+
+impl Parser {
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some()
+            || self.operator.is_some()
+            || self.g
+            || self.macro_key.is_some()
+            || self.quote
+            || self.register.is_some()
+    }
+
+    /// The register named with `"` for the action being typed.
+    pub fn register(&self) -> Option<char> {
+        self.register
+    }
+
+    pub fn feed(&mut self, c: char, visual: bool) -> Parsed {
+        let parsed = self.parse(c, visual);
+        if parsed != Parsed::Pending {
+            *self = Parser::default();
+        }
+        parsed
+    }
+
+    fn parse(&mut self, c: char, visual: bool) -> Parsed {
+        if let Some(key) = self.macro_key.take() {
+            let action = match key {
+                'q' => Action::RecordMacro(c),
+                _ => Action::PlayMacro(c),
+            };
+            let count = self.count.take().unwrap_or(1);
+            return Parsed::Done { count, action };
+        }
+        if self.quote {
+            self.quote = false;
+            self.register = Some(c);
+            return Parsed::Pending;
+        }
+        if c.is_ascii_digit() && (c != '0' || self.count.is_some()) && !self.g {
+            let digit = c.to_digit(10).unwrap_or(0) as usize;
+            self.count = Some(self.count.unwrap_or(0).saturating_mul(10) + digit);
+            return Parsed::Pending;
+        }
+        let count = self.count.take();
+        let motion = if self.g {
+            self.g = false;
+            match c {
+                'g' => Some(Motion::Line(count.unwrap_or(1))),
+                _ => return Parsed::Invalid,
+            }
+        } else {
+            match c {
+                'h' => Some(Motion::Left),
+                'l' | ' ' => Some(Motion::Right),
+                'k' => Some(Motion::Up),
+                'j' | '\n' => Some(Motion::Down),
+                'w' => Some(Motion::WordForward),
+                'b' => Some(Motion::WordBackward),
+                'e' => Some(Motion::WordEnd),
+                '0' => Some(Motion::LineStart),
+                '$' => Some(Motion::LineEnd),
+                'G' => Some(count.map_or(Motion::LastLine, Motion::Line)),
+                'g' => {
+                    self.g = true;
+                    self.count = count;
+                    return Parsed::Pending;
+                }
+                _ => None,
+            }
+        };
+        // the line number of `5G` is not a repeat count.
+        let repeat = match motion {
+            Some(Motion::Line(_)) => None,
+            _ => count,
+        };
+
+        if let Some((operator, operator_count)) = self.operator {
+            let count = repeat.unwrap_or(1) * operator_count.unwrap_or(1);
+            let action = match (motion, operator, c) {
+                (Some(motion), _, _) => Action::Operate(operator, motion),
+                (None, Operator::Delete, 'd')
+                | (None, Operator::Change, 'c')
+                | (None, Operator::Yank, 'y') => Action::OperateLines(operator),
+                _ => return Parsed::Invalid,
+            };
+            return Parsed::Done { count, action };
+        }
+
+        let action = match (motion, c) {
+            (Some(motion), _) => Action::Move(motion),
+            (None, 'd') | (None, 'c') | (None, 'y') => {
+                let operator = match c {
+                    'd' => Operator::Delete,
+                    'c' => Operator::Change,
+                    _ => Operator::Yank,
+                };
+                if visual {
+                    Action::OperateSelection(operator)
+                } else {
+                    self.operator = Some((operator, count));
+                    return Parsed::Pending;
+                }
+            }
+            (None, 'D') => Action::Operate(Operator::Delete, Motion::LineEnd),
+            (None, 'C') => Action::Operate(Operator::Change, Motion::LineEnd),
+            (None, 'x') => Action::DeleteChar,
+            (None, 'i') => Action::Insert(InsertAt::Cursor),
+            (None, 'a') => Action::Insert(InsertAt::After),
+            (None, 'I') => Action::Insert(InsertAt::LineStart),
+            (None, 'A') => Action::Insert(InsertAt::LineEnd),
+            (None, 'o') => Action::Insert(InsertAt::LineBelow),
+            (None, 'O') => Action::Insert(InsertAt::LineAbove),
+            (None, 'p') => Action::Paste { after: true },
+            (None, 'P') => Action::Paste { after: false },
+            (None, 'v') => Action::ToggleVisual,
+            (None, '.') => Action::Repeat,
+            (None, 'u') => Action::Undo,
+            (None, ':') => Action::CommandLine,
+            (None, '"') => {
+                self.quote = true;
+                self.count = count;
+                return Parsed::Pending;
+            }
+            (None, 'q') | (None, '@') if !visual => {
+                self.macro_key = Some(c);
+                self.count = count;
+                return Parsed::Pending;
+            }
+            _ => return Parsed::Invalid,
+        };
+        Parsed::Done {
+            count: repeat.unwrap_or(1),
+            action,
+        }
+    }
+}
+
+/// The state of the modal layer that sits in front of the editor.
+pub struct Vim {
+    pub mode: Mode,
+    pub parser: Parser,
+    /// where visual mode started, as `(x, y)`.
+    pub anchor: (usize, usize),
+    /// the register named with `"` for the action being run.
+    pub register: Option<char>,
+    // keys of the change being typed, and of the last complete one.
+    pub recording: Vec<Key>,
+    pub last_change: Vec<Key>,
+}
+
+impl Default for Vim {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Normal,
+            parser: Parser::default(),
+            anchor: (0, 0),
+            register: None,
+            recording: vec![],
+            last_change: vec![],
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+// walks every position including the one past the end of each row, where
+// the line break is.
+fn char_at(rows: &[Row], (x, y): (usize, usize)) -> char {
+    rows[y].buf.get(x).copied().unwrap_or('\n')
+}
+
+fn next(rows: &[Row], (x, y): (usize, usize)) -> Option<(usize, usize)> {
+    if x < rows[y].buf.len() {
+        Some((x + 1, y))
+    } else if y + 1 < rows.len() {
+        Some((0, y + 1))
+    } else {
+        None
+    }
+}
+
+fn previous(rows: &[Row], (x, y): (usize, usize)) -> Option<(usize, usize)> {
+    if x > 0 {
+        Some((x - 1, y))
+    } else if y > 0 {
+        Some((rows[y - 1].buf.len(), y - 1))
+    } else {
+        None
+    }
+}
+
+fn is_empty_line(rows: &[Row], (x, y): (usize, usize)) -> bool {
+    x == 0 && rows[y].buf.is_empty()
+}
+
+fn word_forward(rows: &[Row], start: (usize, usize)) -> (usize, usize) {
+    let mut p = start;
+    let start_class = class(char_at(rows, p));
+    if start_class != CharClass::Blank {
+        while class(char_at(rows, p)) == start_class {
+            p = match next(rows, p) {
+                Some(p) => p,
+                None => return p,
+            };
+        }
+    }
+    while class(char_at(rows, p)) == CharClass::Blank {
+        if p != start && is_empty_line(rows, p) {
+            break;
+        }
+        p = match next(rows, p) {
+            Some(p) => p,
+            None => return p,
+        };
+    }
+    p
+}
+
+fn word_end(rows: &[Row], start: (usize, usize)) -> (usize, usize) {
+    let mut p = match next(rows, start) {
+        Some(p) => p,
+        None => return start,
+    };
+    while class(char_at(rows, p)) == CharClass::Blank {
+        p = match next(rows, p) {
+            Some(p) => p,
+            None => return p,
+        };
+    }
+    let word_class = class(char_at(rows, p));
+    while let Some(n) = next(rows, p) {
+        if class(char_at(rows, n)) != word_class {
+            break;
+        }
+        p = n;
+    }
+    p
+}
+
+fn word_backward(rows: &[Row], start: (usize, usize)) -> (usize, usize) {
+    let mut p = match previous(rows, start) {
+        Some(p) => p,
+        None => return start,
+    };
+    while class(char_at(rows, p)) == CharClass::Blank && !is_empty_line(rows, p) {
+        p = match previous(rows, p) {
+            Some(p) => p,
+            None => return p,
+        };
+    }
+    let word_class = class(char_at(rows, p));
+    while let Some(n) = previous(rows, p) {
+        if class(char_at(rows, n)) != word_class || word_class == CharClass::Blank {
+            break;
+        }
+        p = n;
+    }
+    p
+}
+
+/// Where `motion` repeated `count` times takes the cursor at `(x, y)`. `x`
+/// may end up one past the last character; normal mode clamps it.
+pub fn apply_motion(
+    rows: &[Row],
+    (x, y): (usize, usize),
+    motion: Motion,
+    count: usize,
+) -> (usize, usize) {
+    if rows.is_empty() {
+        return (0, 0);
+    }
+    let last = rows.len() - 1;
+    let line_len = |y: usize| rows[y].buf.len();
+    match motion {
+        Motion::Left => (x.saturating_sub(count), y),
+        Motion::Right => ((x + count).min(line_len(y)), y),
+        Motion::Up => {
+            let y = y.saturating_sub(count);
+            (x.min(line_len(y)), y)
+        }
+        Motion::Down => {
+            let y = (y + count).min(last);
+            (x.min(line_len(y)), y)
+        }
+        Motion::LineStart => (0, y),
+        Motion::LineEnd => {
+            let y = (y + count - 1).min(last);
+            (line_len(y).saturating_sub(1), y)
+        }
+        Motion::Line(n) => (0, n.clamp(1, rows.len()) - 1),
+        Motion::LastLine => (0, last),
+        Motion::WordForward | Motion::WordBackward | Motion::WordEnd => {
+            let step = match motion {
+                Motion::WordForward => word_forward,
+                Motion::WordBackward => word_backward,
+                _ => word_end,
+            };
+            (0..count).fold((x, y), |p, _| step(rows, p))
+        }
+    }
+}
+
+/// Keeps the cursor on a character, as normal mode does.
+pub fn clamp_to_char(rows: &[Row], (x, y): (usize, usize)) -> (usize, usize) {
+    match rows.get(y) {
+        Some(row) => (x.min(row.buf.len().saturating_sub(1)), y),
+        None => (x, y),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed(keys: &str) -> Vec<Parsed> {
+        let mut parser = Parser::default();
+        keys.chars().map(|c| parser.feed(c, false)).collect()
+    }
+
+    fn done(count: usize, action: Action) -> Parsed {
+        Parsed::Done { count, action }
+    }
+
+    fn rows(lines: &[&str]) -> Vec<Row> {
+        lines
+            .iter()
+            .map(|line| Row::from(&String::from(*line)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(feed("3j").pop(), Some(done(3, Action::Move(Motion::Down))));
+        assert_eq!(
+            feed("2d3w").pop(),
+            Some(done(
+                6,
+                Action::Operate(Operator::Delete, Motion::WordForward)
+            ))
+        );
+        assert_eq!(
+            feed("dd").pop(),
+            Some(done(1, Action::OperateLines(Operator::Delete)))
+        );
+        assert_eq!(feed("dy").pop(), Some(Parsed::Invalid));
+        assert_eq!(
+            feed("0").pop(),
+            Some(done(1, Action::Move(Motion::LineStart)))
+        );
+        assert_eq!(
+            feed("10G").pop(),
+            Some(done(1, Action::Move(Motion::Line(10))))
+        );
+        assert_eq!(
+            feed("gg").pop(),
+            Some(done(1, Action::Move(Motion::Line(1))))
+        );
+        assert_eq!(
+            feed("ygg").pop(),
+            Some(done(1, Action::Operate(Operator::Yank, Motion::Line(1))))
+        );
+        assert_eq!(
+            feed("c$").pop(),
+            Some(done(1, Action::Operate(Operator::Change, Motion::LineEnd)))
+        );
+        assert_eq!(feed("gx").pop(), Some(Parsed::Invalid));
+        assert_eq!(feed("qa").pop(), Some(done(1, Action::RecordMacro('a'))));
+
+        let mut parser = Parser::default();
+        for c in "\"a2".chars() {
+            assert_eq!(parser.feed(c, false), Parsed::Pending);
+        }
+        assert_eq!(parser.register(), Some('a'));
+        assert_eq!(
+            parser.feed('p', false),
+            done(2, Action::Paste { after: true })
+        );
+        assert_eq!(parser.register(), None);
+        assert_eq!(feed("3@@").pop(), Some(done(3, Action::PlayMacro('@'))));
+
+        let mut parser = Parser::default();
+        assert_eq!(
+            parser.feed('d', true),
+            done(1, Action::OperateSelection(Operator::Delete))
+        );
+        assert!(!parser.is_pending());
+    }
+
+    #[test]
+    fn test_word_motions() {
+        let rows = rows(&["foo.bar  baz", "", "  qux"]);
+        let w = |p, n| apply_motion(&rows, p, Motion::WordForward, n);
+        let b = |p, n| apply_motion(&rows, p, Motion::WordBackward, n);
+        let e = |p, n| apply_motion(&rows, p, Motion::WordEnd, n);
+
+        assert_eq!(w((0, 0), 1), (3, 0));
+        assert_eq!(w((0, 0), 3), (9, 0));
+        assert_eq!(w((9, 0), 1), (0, 1));
+        assert_eq!(w((0, 1), 1), (2, 2));
+        assert_eq!(w((2, 2), 1), (5, 2));
+
+        assert_eq!(e((0, 0), 1), (2, 0));
+        assert_eq!(e((2, 0), 1), (3, 0));
+        assert_eq!(e((9, 0), 1), (11, 0));
+        assert_eq!(e((11, 0), 1), (4, 2));
+
+        assert_eq!(b((2, 2), 1), (0, 1));
+        assert_eq!(b((0, 1), 1), (9, 0));
+        assert_eq!(b((9, 0), 2), (3, 0));
+    }
+
+    #[test]
+    fn test_line_motions() {
+        let rows = rows(&["one", "three", "x"]);
+        assert_eq!(apply_motion(&rows, (4, 1), Motion::Down, 5), (1, 2));
+        assert_eq!(apply_motion(&rows, (1, 1), Motion::LineEnd, 1), (4, 1));
+        assert_eq!(apply_motion(&rows, (1, 2), Motion::Line(2), 1), (0, 1));
+        assert_eq!(apply_motion(&rows, (1, 0), Motion::LastLine, 1), (0, 2));
+        assert_eq!(clamp_to_char(&rows, (5, 1)), (4, 1));
+    }
+}